@@ -0,0 +1,99 @@
+use chrono::NaiveDate;
+use criterion::{criterion_group, criterion_main, Criterion};
+use gtfs_structures::{PickupDropOffType, RouteType};
+use std::sync::Arc;
+
+use transpors::cache::Cache;
+use transpors::db::{CustomCalendar, Database, Record};
+
+const SHA256: &str = "0000";
+
+/// Database the size of a busy city stop - a few thousand records sharing
+/// a handful of routes and calendars.
+fn database() -> Database {
+    let start_date = NaiveDate::from_ymd(2026, 1, 1);
+    let calendars: Vec<Arc<CustomCalendar>> = (0..20)
+        .map(|i| {
+            Arc::new(CustomCalendar {
+                monday: i % 2 == 0,
+                tuesday: true,
+                wednesday: true,
+                thursday: true,
+                friday: true,
+                saturday: i % 3 == 0,
+                sunday: i % 5 == 0,
+                start_date,
+                end_date: NaiveDate::from_ymd(2026, 12, 31),
+                added_dates: vec![start_date.succ()],
+                removed_dates: (0..i)
+                    .map(|d| NaiveDate::from_ymd(2026, 6, d + 1))
+                    .collect(),
+            })
+        })
+        .collect();
+
+    let records = (0..5000u32)
+        .map(|i| {
+            let route = i % 30;
+
+            Record {
+                route: route.to_string().into(),
+                route_id: format!("L{}", route).into(),
+                route_long_name: format!("Line {} Central Station - Airport", route).into(),
+                route_type: RouteType::Bus,
+                route_color: Some("FF0000".into()),
+                agency_id: "A".into(),
+                trip: format!("{}_{}", route, i),
+                headsign: Some("Central Station".to_owned()),
+                direction: Some((i % 2) as u8),
+                calendar: calendars[i as usize % calendars.len()].clone(),
+                arrival_time: Some(i * 17),
+                departure_time: Some(i * 17 + 30),
+                pickup_type: PickupDropOffType::Regular,
+                drop_off_type: PickupDropOffType::Regular,
+                first_stop: false,
+                last_stop: false,
+                stop: "Main Square".to_owned(),
+                stop_id: "S1".to_owned(),
+                stop_sequence: 5,
+                position: 4,
+                platform: Some("A".to_owned()),
+                headway: None,
+                trip_start: None,
+            }
+        })
+        .collect();
+
+    Database {
+        records,
+        timezone: "Europe/Prague".to_owned(),
+        validity: None,
+        stop_sequences: (0..5000u32)
+            .map(|i| (format!("{}_{}", i % 30, i), (1..=20).collect()))
+            .collect(),
+    }
+}
+
+fn load(c: &mut Criterion) {
+    let database = database();
+    let yaml = serde_yaml::to_string(&database).unwrap();
+    let binary = Cache::encode(SHA256, vec![("S1", &database)]).unwrap();
+
+    println!(
+        "Cache size: YAML {} kB, binary {} kB",
+        yaml.len() / 1024,
+        binary.len() / 1024
+    );
+
+    let mut group = c.benchmark_group("load");
+    group.bench_function("yaml", |b| {
+        b.iter(|| serde_yaml::from_str::<Database>(&yaml).unwrap())
+    });
+    group.bench_function("binary", |b| {
+        b.iter(|| Cache::decode(&binary, SHA256).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, load);
+criterion_main!(benches);
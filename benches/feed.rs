@@ -0,0 +1,88 @@
+//! Stop databases building. Needs a real (ideally large, national) GTFS
+//! data file given by `TRANSPORS_BENCH_FEED`, e.g.
+//!
+//! ```sh
+//! TRANSPORS_BENCH_FEED=/path/to/gtfs.zip cargo bench --bench feed
+//! ```
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rayon::prelude::*;
+use std::path::PathBuf;
+
+use transpors::db::Database;
+use transpors::feed::Feed;
+
+/// Number of stops databases are built for.
+const STOPS: usize = 50;
+
+fn feed_path() -> Option<PathBuf> {
+    let path = std::env::var_os("TRANSPORS_BENCH_FEED").map(PathBuf::from);
+
+    if path.is_none() {
+        eprintln!("TRANSPORS_BENCH_FEED not set, skipping feed benchmarks");
+    }
+
+    path
+}
+
+fn databases(c: &mut Criterion) {
+    let path = match feed_path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    // Without stops just stops.txt and the small files are read.
+    let mut stop_ids: Vec<String> = Feed::for_stops(&path, &[])
+        .unwrap()
+        .gtfs
+        .stops
+        .into_keys()
+        .collect();
+    stop_ids.sort();
+    stop_ids.truncate(STOPS);
+    let stop_ids: Vec<&str> = stop_ids.iter().map(String::as_str).collect();
+
+    let mut group = c.benchmark_group("feed");
+    group.sample_size(10);
+
+    // How the stops are added or updated: stop times are streamed keeping
+    // just the given stops.
+    group.bench_function("for_stops", |b| {
+        b.iter(|| Feed::for_stops(&path, &stop_ids).unwrap())
+    });
+
+    // Each iteration gets a fresh feed, the index is built once per
+    // feed on the first lookup.
+    group.bench_function("stop_index", |b| {
+        b.iter_batched(
+            || Feed::for_stops(&path, &stop_ids).unwrap(),
+            |feed| {
+                feed.stop_times("");
+                feed
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    // As in `Config::load_databases` - databases built in parallel right
+    // after the feed is parsed, including the index.
+    group.bench_function("databases", |b| {
+        b.iter_batched(
+            || Feed::for_stops(&path, &stop_ids).unwrap(),
+            |feed| {
+                let databases = stop_ids
+                    .par_iter()
+                    .map(|id| Database::from(&feed, &feed.gtfs.stops[*id]))
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap();
+
+                (feed, databases)
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, databases);
+criterion_main!(benches);
@@ -0,0 +1,366 @@
+use gtfs_structures::{PickupDropOffType, RouteType};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+
+use crate::config::{DataFileVersion, FeedSource, Stop};
+use crate::db::{CustomCalendar, Database, Record};
+use crate::error::{Error, Result};
+use crate::feed::Validity;
+
+const CACHE_DIR: &str = "transpors";
+/// Bumped whenever the cache layout (including `Record`) changes, so
+/// caches written by older versions are rebuilt.
+const CACHE_FORMAT: u32 = 6;
+
+/// Where the data file of a feed is kept and which version it is. It's
+/// derived by transpors, so it's stored (as YAML) next to the feed cache
+/// rather than in the config file users edit.
+#[derive(Serialize, Deserialize)]
+struct FeedState<'a> {
+    data_file_path: Cow<'a, Path>,
+    version: Cow<'a, DataFileVersion>,
+}
+
+/// Leading part of the cache file, checked before the rest is decoded.
+#[derive(Deserialize)]
+struct Header<'a> {
+    format: u32,
+    sha256: &'a str,
+}
+
+/// Stop databases of one feed, derived from its data file and stored
+/// (bincode encoded) in the cache directory, so the config file holds
+/// only what user set. Calendars and routes are shared by many records,
+/// so they are stored just once in tables the records refer to by index
+/// and stay shared once decoded. Strings are borrowed from the file
+/// content while decoding.
+#[derive(Serialize, Deserialize)]
+struct FeedCache<'a> {
+    format: u32,
+    /// SHA-256 of the data file the databases were built from.
+    sha256: &'a str,
+    calendars: Vec<CustomCalendar>,
+    #[serde(borrow)]
+    routes: Vec<CachedRoute<'a>>,
+    #[serde(borrow)]
+    databases: Vec<CachedDatabase<'a>>,
+}
+
+#[derive(PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct CachedRoute<'a> {
+    route: &'a str,
+    route_id: &'a str,
+    route_long_name: &'a str,
+    route_type: RouteType,
+    #[serde(borrow)]
+    route_color: Option<&'a str>,
+    agency_id: &'a str,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedDatabase<'a> {
+    stop_id: &'a str,
+    timezone: &'a str,
+    validity: Option<Validity>,
+    #[serde(borrow)]
+    records: Vec<CachedRecord<'a>>,
+    #[serde(borrow)]
+    stop_sequences: Vec<(&'a str, Vec<u16>)>,
+}
+
+/// Decoded route - name, ID, long name, type, color and agency ID.
+type SharedRoute = (
+    Arc<str>,
+    Arc<str>,
+    Arc<str>,
+    RouteType,
+    Option<Arc<str>>,
+    Arc<str>,
+);
+
+/// `Record` with calendar and route replaced by table indexes.
+#[derive(Serialize, Deserialize)]
+struct CachedRecord<'a> {
+    route: u32,
+    calendar: u32,
+    trip: &'a str,
+    #[serde(borrow)]
+    headsign: Option<&'a str>,
+    direction: Option<u8>,
+    arrival_time: Option<u32>,
+    departure_time: Option<u32>,
+    pickup_type: PickupDropOffType,
+    drop_off_type: PickupDropOffType,
+    first_stop: bool,
+    last_stop: bool,
+    stop: &'a str,
+    stop_id: &'a str,
+    stop_sequence: u16,
+    position: u16,
+    #[serde(borrow)]
+    platform: Option<&'a str>,
+    headway: Option<u32>,
+    trip_start: Option<u32>,
+}
+
+/// Access to the feed caches and states.
+pub struct Cache;
+
+impl Cache {
+    /// Loads stop databases of the feed (stop ID -> database). Returns
+    /// `None` if there is no cache or it can't be used - it's unreadable,
+    /// of another format or built from another data file version.
+    pub async fn load(feed: &FeedSource) -> Option<HashMap<String, Database>> {
+        let content = fs::read(Self::path(&feed.name, "cache").ok()?).await.ok()?;
+
+        Self::decode(&content, &feed.version.sha256)
+    }
+
+    /// Saves databases of the given feed stops.
+    pub async fn save(feed: &FeedSource, stops: &[Stop]) -> Result<()> {
+        let path = Self::path(&feed.name, "cache")?;
+        let databases = stops
+            .iter()
+            .filter(|s| s.feed == feed.name)
+            .map(|s| (s.id.as_str(), &s.database));
+        let content =
+            Self::encode(&feed.version.sha256, databases).map_err(|err| Error::Cache {
+                path: path.clone(),
+                message: err.to_string(),
+            })?;
+
+        Self::write(path, content).await
+    }
+
+    /// Loads data file path and version of the feed. Returns `None` if
+    /// there is no state or it can't be read.
+    pub async fn load_state(feed_name: &str) -> Option<(PathBuf, DataFileVersion)> {
+        let content = fs::read(Self::path(feed_name, "state").ok()?).await.ok()?;
+        let state: FeedState = serde_yaml::from_slice(&content).ok()?;
+
+        Some((
+            state.data_file_path.into_owned(),
+            state.version.into_owned(),
+        ))
+    }
+
+    /// Saves data file path and version of the feed.
+    pub async fn save_state(feed: &FeedSource) -> Result<()> {
+        let path = Self::path(&feed.name, "state")?;
+        let content = serde_yaml::to_vec(&FeedState {
+            data_file_path: Cow::Borrowed(&feed.data_file_path),
+            version: Cow::Borrowed(&feed.version),
+        })
+        .map_err(|err| Error::Cache {
+            path: path.clone(),
+            message: err.to_string(),
+        })?;
+
+        Self::write(path, content).await
+    }
+
+    /// Encodes stop databases (stop ID, database) built from data file
+    /// with the given SHA-256.
+    pub fn encode<'a>(
+        sha256: &str,
+        databases: impl IntoIterator<Item = (&'a str, &'a Database)>,
+    ) -> bincode::Result<Vec<u8>> {
+        let mut calendars: HashMap<&CustomCalendar, u32> = HashMap::new();
+        let mut routes: HashMap<CachedRoute, u32> = HashMap::new();
+        let mut cached_databases = vec![];
+
+        for (stop_id, database) in databases {
+            let mut records = vec![];
+
+            for record in database.records.iter() {
+                let next = calendars.len() as u32;
+                let calendar = *calendars.entry(&record.calendar).or_insert(next);
+                let next = routes.len() as u32;
+                let route = *routes
+                    .entry(CachedRoute {
+                        route: &record.route,
+                        route_id: &record.route_id,
+                        route_long_name: &record.route_long_name,
+                        route_type: record.route_type,
+                        route_color: record.route_color.as_deref(),
+                        agency_id: &record.agency_id,
+                    })
+                    .or_insert(next);
+
+                records.push(CachedRecord {
+                    route,
+                    calendar,
+                    trip: &record.trip,
+                    headsign: record.headsign.as_deref(),
+                    direction: record.direction,
+                    arrival_time: record.arrival_time,
+                    departure_time: record.departure_time,
+                    pickup_type: record.pickup_type,
+                    drop_off_type: record.drop_off_type,
+                    first_stop: record.first_stop,
+                    last_stop: record.last_stop,
+                    stop: &record.stop,
+                    stop_id: &record.stop_id,
+                    stop_sequence: record.stop_sequence,
+                    position: record.position,
+                    platform: record.platform.as_deref(),
+                    headway: record.headway,
+                    trip_start: record.trip_start,
+                });
+            }
+
+            cached_databases.push(CachedDatabase {
+                stop_id,
+                timezone: &database.timezone,
+                validity: database.validity,
+                records,
+                stop_sequences: database
+                    .stop_sequences
+                    .iter()
+                    .map(|(trip, sequences)| (trip.as_str(), sequences.clone()))
+                    .collect(),
+            });
+        }
+
+        bincode::serialize(&FeedCache {
+            format: CACHE_FORMAT,
+            sha256,
+            calendars: Self::table(calendars).into_iter().cloned().collect(),
+            routes: Self::table(routes),
+            databases: cached_databases,
+        })
+    }
+
+    /// Decodes stop databases (stop ID -> database). Returns `None` if the
+    /// content is not a cache of the given data file SHA-256 in the
+    /// current format.
+    pub fn decode(content: &[u8], sha256: &str) -> Option<HashMap<String, Database>> {
+        // Header is checked first, the rest may be in another format.
+        let header: Header = bincode::deserialize(content).ok()?;
+
+        if header.format != CACHE_FORMAT || header.sha256 != sha256 {
+            return None;
+        }
+
+        let cache: FeedCache = bincode::deserialize(content).ok()?;
+        let calendars: Vec<Arc<CustomCalendar>> =
+            cache.calendars.into_iter().map(Arc::new).collect();
+        // Route strings are shared by the records the same way.
+        let routes: Vec<SharedRoute> = cache
+            .routes
+            .iter()
+            .map(|r| {
+                (
+                    r.route.into(),
+                    r.route_id.into(),
+                    r.route_long_name.into(),
+                    r.route_type,
+                    r.route_color.map(Arc::from),
+                    r.agency_id.into(),
+                )
+            })
+            .collect();
+        let mut databases = HashMap::new();
+
+        for database in cache.databases {
+            let mut records = Vec::with_capacity(database.records.len());
+
+            for record in database.records {
+                let (route, route_id, route_long_name, route_type, route_color, agency_id) =
+                    routes.get(record.route as usize)?;
+
+                records.push(Record {
+                    route: route.clone(),
+                    route_id: route_id.clone(),
+                    route_long_name: route_long_name.clone(),
+                    route_type: *route_type,
+                    route_color: route_color.clone(),
+                    agency_id: agency_id.clone(),
+                    trip: record.trip.to_owned(),
+                    headsign: record.headsign.map(str::to_owned),
+                    direction: record.direction,
+                    calendar: calendars.get(record.calendar as usize)?.clone(),
+                    arrival_time: record.arrival_time,
+                    departure_time: record.departure_time,
+                    pickup_type: record.pickup_type,
+                    drop_off_type: record.drop_off_type,
+                    first_stop: record.first_stop,
+                    last_stop: record.last_stop,
+                    stop: record.stop.to_owned(),
+                    stop_id: record.stop_id.to_owned(),
+                    stop_sequence: record.stop_sequence,
+                    position: record.position,
+                    platform: record.platform.map(str::to_owned),
+                    headway: record.headway,
+                    trip_start: record.trip_start,
+                });
+            }
+
+            databases.insert(
+                database.stop_id.to_owned(),
+                Database {
+                    records,
+                    timezone: database.timezone.to_owned(),
+                    validity: database.validity,
+                    stop_sequences: database
+                        .stop_sequences
+                        .into_iter()
+                        .map(|(trip, sequences)| (trip.to_owned(), sequences))
+                        .collect(),
+                },
+            );
+        }
+
+        Some(databases)
+    }
+
+    /// Removes cache and state of the feed (if any).
+    pub async fn remove(feed_name: &str) -> Result<()> {
+        for extension in &["cache", "state"] {
+            let path = Self::path(feed_name, extension)?;
+
+            if path.exists() {
+                fs::remove_file(&path)
+                    .await
+                    .map_err(Error::config_io(path))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes file to the cache directory.
+    async fn write(path: PathBuf, content: Vec<u8>) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .await
+                .map_err(Error::config_io(dir))?;
+        }
+
+        fs::write(&path, content)
+            .await
+            .map_err(Error::config_io(path))
+    }
+
+    /// Orders table entries by their index.
+    fn table<T>(entries: HashMap<T, u32>) -> Vec<T> {
+        let mut entries: Vec<(T, u32)> = entries.into_iter().collect();
+        entries.sort_by_key(|(_, i)| *i);
+
+        entries.into_iter().map(|(entry, _)| entry).collect()
+    }
+
+    /// Path of the feed cache ("cache") or state ("state") file.
+    fn path(feed_name: &str, extension: &str) -> Result<PathBuf> {
+        let mut dir = dirs::cache_dir().ok_or_else(|| {
+            Error::InvalidInput("No cache directory available, set HOME".to_owned())
+        })?;
+        dir.push(CACHE_DIR);
+
+        Ok(dir.join(format!("{}.{}", feed_name, extension)))
+    }
+}
@@ -0,0 +1,240 @@
+use chrono::{Local, NaiveDateTime, NaiveTime};
+use std::net::SocketAddr;
+use structopt::StructOpt;
+
+use transpors::db::RouteDirection;
+use transpors::output::Format;
+use transpors::timetables::MAX_WINDOW_MINUTES;
+
+/// Command line options.
+#[derive(StructOpt)]
+#[structopt(name = "transpors", about = "Shows next departures from your stops.")]
+pub struct Opt {
+    #[structopt(flatten)]
+    pub show: ShowOpt,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    /// Sets up config with the given data file (replaces existing config).
+    Init {
+        /// GTFS data file path or URL.
+        #[structopt(long)]
+        feed: String,
+
+        /// Feed name.
+        #[structopt(long)]
+        name: Option<String>,
+    },
+
+    /// Manages feeds (GTFS data files).
+    Feed(FeedCommand),
+
+    /// Manages stops.
+    Stop(StopCommand),
+
+    /// Fetches feeds again and rebuilds stop databases if they changed.
+    Update {
+        /// Feed to update, defaults to all feeds.
+        feed: Option<String>,
+    },
+
+    /// Shows next departures (default).
+    Show(ShowOpt),
+
+    /// Shows full screen departure board that keeps updating.
+    Watch(WatchOpt),
+
+    /// Serves departures as JSON over HTTP.
+    Serve {
+        /// Address to listen on.
+        #[structopt(long, default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
+
+        /// How often realtime data are fetched again (in seconds).
+        #[structopt(long, default_value = "30")]
+        realtime_interval: u64,
+
+        /// Doesn't fetch realtime data, serves just the schedule.
+        #[structopt(long)]
+        no_realtime: bool,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum FeedCommand {
+    /// Adds feed with the given name.
+    Add {
+        name: String,
+
+        /// GTFS data file path or URL.
+        path_or_url: String,
+    },
+
+    /// Removes feed together with its stops.
+    Remove { name: String },
+
+    /// Sets GTFS Realtime sources of the feed (URLs or local file paths).
+    Realtime {
+        name: String,
+
+        /// TripUpdates source, empty string removes it.
+        #[structopt(long)]
+        trip_updates: Option<String>,
+
+        /// Alerts source, empty string removes it.
+        #[structopt(long)]
+        alerts: Option<String>,
+
+        /// VehiclePositions source, empty string removes it.
+        #[structopt(long)]
+        vehicle_positions: Option<String>,
+    },
+
+    /// Lists configured feeds.
+    List,
+}
+
+#[derive(StructOpt)]
+pub enum StopCommand {
+    /// Adds stop given by stop ID or name.
+    Add {
+        stop: String,
+
+        /// Feed to search the stop in, defaults to all feeds.
+        #[structopt(long)]
+        feed: Option<String>,
+    },
+
+    /// Removes stop given by stop ID or name.
+    Remove { stop: String },
+
+    /// Searches stops by name (ignoring case, diacritics and typos), stop
+    /// ID or stop code.
+    Search {
+        query: String,
+
+        /// Feed to search in, defaults to all feeds.
+        #[structopt(long)]
+        feed: Option<String>,
+
+        /// Maximum number of stops shown.
+        #[structopt(long, default_value = "10")]
+        limit: usize,
+    },
+
+    /// Shows only departures of the given routes and route directions
+    /// from the stop. Without any lists directions served from the stop.
+    Filter {
+        /// Stop ID or name.
+        stop: String,
+
+        /// Route to show, may be repeated.
+        #[structopt(long = "route")]
+        routes: Vec<String>,
+
+        /// Route direction to show as "ROUTE:HEADSIGN", may be repeated.
+        #[structopt(
+            long = "direction",
+            value_name = "route:headsign",
+            parse(try_from_str = parse_direction)
+        )]
+        directions: Vec<RouteDirection>,
+
+        /// Removes filters, so all departures are shown again.
+        #[structopt(long, conflicts_with_all = &["routes", "directions"])]
+        clear: bool,
+    },
+
+    /// Lists configured stops.
+    List,
+}
+
+#[derive(StructOpt)]
+pub struct ShowOpt {
+    /// Reference time as "YYYY-MM-DD HH:MM" or "HH:MM" (today), defaults to now.
+    #[structopt(long, parse(try_from_str = parse_datetime))]
+    pub at: Option<NaiveDateTime>,
+
+    /// Maximum number of departures shown per stop.
+    #[structopt(long, default_value = "10")]
+    pub limit: usize,
+
+    /// How far ahead to look for departures (in minutes, at most 2880).
+    #[structopt(long, default_value = "120", parse(try_from_str = parse_window))]
+    pub window: i64,
+
+    /// Shows arrivals instead of departures.
+    #[structopt(long)]
+    pub arrivals: bool,
+
+    /// Shows also trips you can't board (e.g. terminating at the stop).
+    #[structopt(long)]
+    pub all: bool,
+
+    /// Merges departures from all stops into one board.
+    #[structopt(long)]
+    pub merge: bool,
+
+    /// Doesn't fetch realtime data, shows just the schedule.
+    #[structopt(long)]
+    pub no_realtime: bool,
+
+    /// Output format: text, json, csv or ndjson.
+    #[structopt(long, default_value = "text")]
+    pub format: Format,
+}
+
+#[derive(StructOpt)]
+pub struct WatchOpt {
+    #[structopt(flatten)]
+    pub show: ShowOpt,
+
+    /// How often the board is redrawn (in seconds).
+    #[structopt(long, default_value = "5")]
+    pub interval: u64,
+
+    /// How often realtime data are fetched again (in seconds).
+    #[structopt(long, default_value = "30")]
+    pub realtime_interval: u64,
+}
+
+/// Parses reference time. Time without date means today.
+pub fn parse_datetime(input: &str) -> Result<NaiveDateTime, chrono::ParseError> {
+    match NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M") {
+        Ok(datetime) => Ok(datetime),
+        Err(err) => match NaiveTime::parse_from_str(input, "%H:%M") {
+            Ok(time) => Ok(Local::today().naive_local().and_time(time)),
+            Err(_) => Err(err),
+        },
+    }
+}
+
+/// Parses look-ahead window in minutes, which must be within 0 and
+/// `MAX_WINDOW_MINUTES`.
+pub fn parse_window(input: &str) -> Result<i64, String> {
+    match input.parse::<i64>() {
+        Ok(window) if (0..=MAX_WINDOW_MINUTES).contains(&window) => Ok(window),
+        _ => Err(format!(
+            "Expected minutes between 0 and {}, got {}",
+            MAX_WINDOW_MINUTES, input
+        )),
+    }
+}
+
+/// Parses route direction given as "ROUTE:HEADSIGN" (headsign may be
+/// empty for trips without one).
+pub fn parse_direction(input: &str) -> Result<RouteDirection, String> {
+    match input.split_once(':') {
+        Some((route, headsign)) if !route.trim().is_empty() => Ok(RouteDirection {
+            route: route.trim().to_owned(),
+            headsign: Some(headsign.trim())
+                .filter(|h| !h.is_empty())
+                .map(str::to_owned),
+        }),
+        _ => Err(format!("Expected ROUTE:HEADSIGN, got {}", input)),
+    }
+}
@@ -1,111 +1,741 @@
+use rayon::prelude::*;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
-use serde_yaml;
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 use tokio::fs::{self, File};
 use tokio::prelude::*;
 
-use crate::db::Database;
-use crate::ui::{Wizard, WizardOutput};
+use crate::cache::Cache;
+use crate::db::{Database, Record, RouteDirection};
+use crate::error::{Error, Result};
+use crate::feed::{Feed, Validity};
 
 const CONF_DIR: &str = "transpors";
 const CONF_FILE: &str = "config.yaml";
+/// Name of the feed created when none is given.
+pub const DEFAULT_FEED: &str = "default";
 
+/// GTFS data file (feed) stops are taken from.
+#[derive(Serialize, Deserialize)]
+pub struct FeedSource {
+    /// Name the feed is referred to by (unique).
+    pub name: String,
+    /// URL or local path the data file is fetched from.
+    pub data_file_url: String,
+    /// Where the fetched data file is kept. Derived like `version`, so
+    /// both are kept in the feed state (see `Cache::save_state`) instead
+    /// of the config file. Config files that still have them are
+    /// migrated.
+    #[serde(default, skip_serializing)]
+    pub data_file_path: PathBuf,
+    /// Version of the kept data file.
+    #[serde(default, skip_serializing)]
+    pub version: DataFileVersion,
+    /// GTFS Realtime TripUpdates URL (or local file path).
+    #[serde(default)]
+    pub trip_updates_url: Option<String>,
+    /// GTFS Realtime Alerts URL (or local file path).
+    #[serde(default)]
+    pub alerts_url: Option<String>,
+    /// GTFS Realtime VehiclePositions URL (or local file path).
+    #[serde(default)]
+    pub vehicle_positions_url: Option<String>,
+}
+
+impl FeedSource {
+    /// Tells whether data file path is known, i.e. the feed state has
+    /// been filled (or given by config file of an older layout).
+    fn has_state(&self) -> bool {
+        !self.data_file_path.as_os_str().is_empty()
+    }
+}
+
+/// HTTP validators and content hash used to detect data file changes.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct DataFileVersion {
+    /// ETag header of the download.
+    pub etag: Option<String>,
+    /// Last-Modified header of the download.
+    pub last_modified: Option<String>,
+    /// SHA-256 (hex) of the data file content.
+    pub sha256: String,
+}
+
+/// Outcome of a feed update.
+pub enum FeedUpdate {
+    /// Data file is the same, nothing was rebuilt.
+    Unchanged,
+    /// New data file replaced the old one and stop databases were rebuilt.
+    Updated {
+        /// Route names not in the old data file.
+        added_routes: Vec<String>,
+        /// Route names not in the new data file.
+        removed_routes: Vec<String>,
+        /// Service period of the old data file (if known).
+        old_validity: Option<Validity>,
+        /// Service period of the new data file.
+        new_validity: Option<Validity>,
+        /// Stops that are no longer in the feed (their database is kept).
+        missing_stops: Vec<String>,
+    },
+}
+
+/// Stop the user watches together with its prebuilt database.
 #[derive(Serialize, Deserialize)]
 pub struct Stop {
-    id: String,
+    /// GTFS stop ID.
+    pub id: String,
+    /// Stop name as shown on boards.
     pub name: String,
+    /// Name of the feed the stop belongs to. Config files from before
+    /// feeds don't have it, such stops belong to the first feed.
+    #[serde(default)]
+    pub feed: String,
+    /// Platform code if the stop is a single platform of a station.
+    #[serde(default)]
+    pub platform: Option<String>,
+    /// Routes whose departures are shown (in any direction). If neither
+    /// routes nor directions are set, all departures are shown.
+    #[serde(default)]
+    pub routes: Vec<String>,
+    /// Route directions whose departures are shown, besides `routes`.
+    #[serde(default)]
+    pub directions: Vec<RouteDirection>,
+    /// Derived from the feed data file, kept in the cache (see `Cache`)
+    /// instead of the config file.
+    #[serde(skip)]
     pub database: Database,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Config {
+impl Stop {
+    /// Stop name with platform (if any), e.g. "Main Station, platform 2".
+    pub fn display_name(&self) -> String {
+        match &self.platform {
+            Some(platform) => format!("{}, platform {}", self.name, platform),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Tells whether departures of the record pass the stop route and
+    /// direction filters.
+    pub fn shows(&self, record: &Record) -> bool {
+        self.shows_direction(&record.route, record.headsign.as_deref())
+    }
+
+    /// Tells whether departures of the route with the given headsign pass
+    /// the stop filters - the route or the route direction is listed.
+    /// Without any filters everything is shown.
+    pub fn shows_direction(&self, route: &str, headsign: Option<&str>) -> bool {
+        if self.routes.is_empty() && self.directions.is_empty() {
+            return true;
+        }
+
+        self.routes.iter().any(|r| r == route)
+            || self
+                .directions
+                .iter()
+                .any(|d| d.route == route && d.headsign.as_deref() == headsign)
+    }
+
+    /// Sets route and direction filters, empty ones clear them. Routes and
+    /// directions not departing from the stop are rejected.
+    pub fn set_filters(
+        &mut self,
+        routes: Vec<String>,
+        directions: Vec<RouteDirection>,
+    ) -> Result<()> {
+        let served = self.database.directions();
+
+        if let Some(route) = routes
+            .iter()
+            .find(|r| !served.iter().any(|d| &d.route == *r))
+        {
+            return Err(Error::InvalidInput(format!(
+                "Route {} doesn't depart from {}",
+                route,
+                self.display_name()
+            )));
+        }
+
+        if let Some(direction) = directions.iter().find(|d| !served.contains(d)) {
+            return Err(Error::InvalidInput(format!(
+                "Route {} doesn't depart from {}",
+                direction,
+                self.display_name()
+            )));
+        }
+
+        self.routes = routes;
+        self.directions = directions;
+
+        Ok(())
+    }
+}
+
+/// Config file layout from before feeds, with one data file all stops
+/// are taken from. It's migrated to the default feed.
+#[derive(Deserialize)]
+struct LegacyConfig {
     data_file_url: String,
     data_file_path: PathBuf,
+    #[serde(default)]
+    stops: Vec<Stop>,
+}
+
+/// User configuration - feeds and stops (stored as YAML in the config
+/// directory).
+#[derive(Serialize, Deserialize)]
+pub struct Config {
+    /// Configured feeds (GTFS data files).
+    pub feeds: Vec<FeedSource>,
+    /// Watched stops in the order they are shown.
     pub stops: Vec<Stop>,
 }
 
 impl Config {
-    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let config: Self;
+    /// Creates and saves config with the default feed, whose data file
+    /// has been already retrieved to `data_file_path(DEFAULT_FEED)`, and
+    /// the given stops of it (with databases built).
+    pub async fn create(
+        data_file_url: &str,
+        version: DataFileVersion,
+        stops: Vec<Stop>,
+    ) -> Result<Self> {
+        let config = Self {
+            feeds: vec![FeedSource {
+                name: DEFAULT_FEED.to_owned(),
+                data_file_url: data_file_url.to_owned(),
+                data_file_path: Self::data_file_path(DEFAULT_FEED)?,
+                version,
+                trip_updates_url: None,
+                alerts_url: None,
+                vehicle_positions_url: None,
+            }],
+            stops,
+        };
 
-        // Determine config path.
-        let mut dir = dirs::config_dir().expect("Config directory is not available.");
-        dir.push(CONF_DIR);
+        config.save().await?;
 
-        let mut conf_file_path = dir.clone();
-        conf_file_path.push(CONF_FILE);
+        Ok(config)
+    }
 
-        // Check if config exists.
-        if !dir.exists() {
-            Self::create_conf_dir(&dir).await?;
-            let mut wiz = Wizard::new(&dir).await;
-            let output = wiz.run_wizard().await.expect("Wizard failed. Exiting.");
+    /// Loads config from config file together with cached stop databases.
+    /// Returns `None` if there is no config file yet.
+    pub async fn load() -> Result<Option<Self>> {
+        let conf_file_path = Self::conf_dir()?.join(CONF_FILE);
 
-            let stops = Self::build_stops_database(&output).await;
-            // println!("{:?}", stops);
+        if !conf_file_path.exists() {
+            return Ok(None);
+        }
 
-            config = Self {
-                data_file_url: wiz.data_file_url.unwrap(),
-                data_file_path: wiz.data_file_path.unwrap().clone(),
-                stops,
-            };
+        // Load config file.
+        let mut file_content = String::new();
+        File::open(&conf_file_path)
+            .await
+            .map_err(Error::config_io(&conf_file_path))?
+            .read_to_string(&mut file_content)
+            .await
+            .map_err(Error::config_io(&conf_file_path))?;
 
-            config.save(&conf_file_path).await?;
-        } else {
-            // Load config file.
-            let mut config_file = File::open(&conf_file_path).await?;
-            let mut file_content = String::new();
-            config_file.read_to_string(&mut file_content).await?;
+        // Construct Self.
+        let (mut config, migrated) =
+            Self::parse(&file_content).map_err(|source| Error::ConfigParse {
+                path: conf_file_path,
+                source,
+            })?;
+        config.load_states().await?;
+        config.load_databases().await?;
 
-            // Construct Self.
-            config = serde_yaml::from_str(&file_content)?;
+        // Migrated config is stored in the current layout right away.
+        if migrated {
+            config.save().await?;
         }
 
-        // Debug output
-        for stop in &config.stops {
-            println!(
-                "Stop {} has {} records in database",
-                &stop.name,
-                stop.database.records.len()
-            );
+        Ok(Some(config))
+    }
+
+    /// Parses config file content. Config files from before feeds (see
+    /// `LegacyConfig`) or with feed states in them are migrated, the
+    /// returned flag tells so.
+    fn parse(content: &str) -> serde_yaml::Result<(Self, bool)> {
+        let error = match serde_yaml::from_str::<Self>(content) {
+            Ok(mut config) => {
+                if let Some(feed) = config.feeds.first() {
+                    for stop in config.stops.iter_mut().filter(|s| s.feed.is_empty()) {
+                        stop.feed = feed.name.clone();
+                    }
+                }
+
+                let migrated = config.feeds.iter().any(|f| f.has_state());
+
+                return Ok((config, migrated));
+            }
+            Err(err) => err,
+        };
+
+        // The current layout error is more telling for broken files.
+        let legacy: LegacyConfig = serde_yaml::from_str(content).map_err(|_| error)?;
+        let mut stops = legacy.stops;
+
+        for stop in stops.iter_mut() {
+            stop.feed = DEFAULT_FEED.to_owned();
         }
 
-        Ok(config)
+        let config = Self {
+            feeds: vec![FeedSource {
+                name: DEFAULT_FEED.to_owned(),
+                data_file_url: legacy.data_file_url,
+                data_file_path: legacy.data_file_path,
+                version: DataFileVersion::default(),
+                trip_updates_url: None,
+                alerts_url: None,
+                vehicle_positions_url: None,
+            }],
+            stops,
+        };
+
+        Ok((config, true))
     }
 
-    /// Creates config directory and returns path to that directory.
-    async fn create_conf_dir(path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-        if !path.exists() {
-            fs::create_dir_all(&path).await?;
+    /// Fills data file paths and versions of feeds from their states
+    /// unless the config file had them. Feeds without state get the
+    /// default data file path and no version, so their caches are
+    /// rebuilt and next update fetches the data file in full.
+    async fn load_states(&mut self) -> Result<()> {
+        for feed in self.feeds.iter_mut().filter(|f| !f.has_state()) {
+            match Cache::load_state(&feed.name).await {
+                Some((data_file_path, version)) => {
+                    feed.data_file_path = data_file_path;
+                    feed.version = version;
+                }
+                None => feed.data_file_path = Self::data_file_path(&feed.name)?,
+            }
         }
 
         Ok(())
     }
 
-    /// Saves config (serialize) to config YAML file.
-    async fn save(&self, path: &PathBuf) -> Result<(), std::io::Error> {
-        fs::write(
-            path,
-            serde_yaml::to_string(self).expect("Couldn't serialize config."),
+    /// Fills stop databases from the feed caches. Caches that are missing,
+    /// outdated or lack some stop are rebuilt from the data file.
+    async fn load_databases(&mut self) -> Result<()> {
+        for feed in self.feeds.iter() {
+            let mut cached = Cache::load(feed).await.unwrap_or_default();
+            let complete = self
+                .stops
+                .iter()
+                .filter(|s| s.feed == feed.name)
+                .all(|s| cached.contains_key(&s.id));
+            let stops = self.stops.iter_mut().filter(|s| s.feed == feed.name);
+
+            if complete {
+                for stop in stops {
+                    stop.database = cached.remove(&stop.id).unwrap_or_default();
+                }
+
+                continue;
+            }
+
+            let mut stops: Vec<&mut Stop> = stops.collect();
+            let stop_ids: Vec<&str> = stops.iter().map(|s| s.id.as_str()).collect();
+            let gtfs_feed = Feed::for_stops(&feed.data_file_path, &stop_ids)?;
+
+            stops.par_iter_mut().try_for_each(|stop| -> Result<()> {
+                stop.database = match gtfs_feed.gtfs.stops.get(&stop.id) {
+                    Some(gtfs_stop) => Database::from(&gtfs_feed, gtfs_stop)?,
+                    // Stop no longer in the feed, nothing to build from.
+                    None => Database::default(),
+                };
+
+                Ok(())
+            })?;
+
+            Cache::save(feed, &self.stops).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates new config (without stops) for the given data file.
+    pub async fn init(name: Option<&str>, path_or_url: &str) -> Result<Self> {
+        let mut config = Self {
+            feeds: vec![],
+            stops: vec![],
+        };
+
+        config
+            .add_feed(name.unwrap_or(DEFAULT_FEED), path_or_url)
+            .await?;
+        config.save().await?;
+
+        Ok(config)
+    }
+
+    /// Retrieves data file and adds it to config as a new feed.
+    pub async fn add_feed(&mut self, name: &str, path_or_url: &str) -> Result<&FeedSource> {
+        // Name is a part of the data file and cache file names.
+        if name.is_empty() || name.contains("..") || name.contains(&['/', '\\'][..]) {
+            return Err(Error::InvalidInput(format!(
+                "Invalid feed name {:?}, use a name without slashes and \"..\"",
+                name
+            )));
+        }
+
+        if self.feeds.iter().any(|f| f.name == name) {
+            return Err(Error::AlreadyExists(format!("Feed {}", name)));
+        }
+
+        // Retrieve into a temporary file, so a data file that can't be
+        // used doesn't replace the current one (re-init) or stay behind.
+        let data_file_path = Self::data_file_path(name)?;
+        let new_path = data_file_path.with_extension("gtfs.new");
+        let retrieved = match Self::download_or_copy_data_file(&new_path, path_or_url, None).await {
+            // Make sure the data file is usable.
+            Ok(version) => Feed::for_stops(&new_path, &[]).map(|_| version.unwrap_or_default()),
+            Err(err) => Err(err),
+        };
+        let version = match retrieved {
+            Ok(version) => version,
+            Err(err) => {
+                Self::remove_new_data_file(&new_path).await?;
+
+                return Err(err);
+            }
+        };
+
+        fs::rename(&new_path, &data_file_path)
+            .await
+            .map_err(Error::config_io(&data_file_path))?;
+
+        self.feeds.push(FeedSource {
+            name: name.to_owned(),
+            data_file_url: path_or_url.to_owned(),
+            data_file_path,
+            version,
+            trip_updates_url: None,
+            alerts_url: None,
+            vehicle_positions_url: None,
+        });
+
+        Ok(self.feeds.last().unwrap())
+    }
+
+    /// Removes feed together with all its stops.
+    pub async fn remove_feed(&mut self, name: &str) -> Result<FeedSource> {
+        let i = self
+            .feeds
+            .iter()
+            .position(|f| f.name == name)
+            .ok_or_else(|| Error::UnknownFeed(name.to_owned()))?;
+
+        let feed = self.feeds.remove(i);
+        self.stops.retain(|s| s.feed != feed.name);
+        Cache::remove(&feed.name).await?;
+
+        if feed.data_file_path.exists() {
+            fs::remove_file(&feed.data_file_path)
+                .await
+                .map_err(Error::config_io(&feed.data_file_path))?;
+        }
+
+        Ok(feed)
+    }
+
+    /// Fetches data file of the given feed again and if it has changed
+    /// rebuilds databases of all stops of the feed.
+    pub async fn update_feed(&mut self, name: &str) -> Result<FeedUpdate> {
+        let source = self
+            .feeds
+            .iter_mut()
+            .find(|f| f.name == name)
+            .ok_or_else(|| Error::UnknownFeed(name.to_owned()))?;
+
+        // Fetch into a temporary file so the current one stays intact
+        // until the new one is known to be usable.
+        let new_path = source.data_file_path.with_extension("gtfs.new");
+        let retrieved = Self::download_or_copy_data_file(
+            &new_path,
+            &source.data_file_url,
+            Some(&source.version),
         )
-        .await
+        .await;
+        let version = match retrieved {
+            Err(err) => {
+                // Download may fail half way.
+                Self::remove_new_data_file(&new_path).await?;
+
+                return Err(err);
+            }
+            Ok(Some(version)) if version.sha256 != source.version.sha256 => version,
+            // Not modified (HTTP 304) or same content. The server may
+            // still have sent new validators, keep them for next time.
+            Ok(version) => {
+                if let Some(version) = version {
+                    source.version = version;
+                }
+
+                Self::remove_new_data_file(&new_path).await?;
+
+                return Ok(FeedUpdate::Unchanged);
+            }
+        };
+
+        let stop_ids: Vec<&str> = self
+            .stops
+            .iter()
+            .filter(|s| s.feed == name)
+            .map(|s| s.id.as_str())
+            .collect();
+        let new_feed = match Feed::for_stops(&new_path, &stop_ids) {
+            Ok(new_feed) => new_feed,
+            Err(err) => {
+                Self::remove_new_data_file(&new_path).await?;
+
+                return Err(err);
+            }
+        };
+        let new_routes = new_feed.route_names();
+        let new_validity = new_feed.validity();
+
+        let (old_routes, old_validity) = match Feed::for_stops(&source.data_file_path, &[]) {
+            Ok(old_feed) => (old_feed.route_names(), old_feed.validity()),
+            Err(_) => (BTreeSet::new(), None),
+        };
+
+        fs::rename(&new_path, &source.data_file_path)
+            .await
+            .map_err(Error::config_io(&source.data_file_path))?;
+        source.version = version;
+
+        // Rebuild databases of the feed stops.
+        let mut missing_stops = vec![];
+
+        for stop in self.stops.iter_mut().filter(|s| s.feed == name) {
+            match new_feed.gtfs.stops.get(&stop.id) {
+                Some(gtfs_stop) => stop.database = Database::from(&new_feed, gtfs_stop)?,
+                None => missing_stops.push(stop.name.clone()),
+            }
+        }
+
+        Ok(FeedUpdate::Updated {
+            added_routes: new_routes.difference(&old_routes).cloned().collect(),
+            removed_routes: old_routes.difference(&new_routes).cloned().collect(),
+            old_validity,
+            new_validity,
+            missing_stops,
+        })
     }
 
-    /// Builds up stop database for each stop from config.
-    async fn build_stops_database(output: &WizardOutput) -> Vec<Stop> {
-        let mut stops = vec![];
+    /// Removes fetched data file that won't replace the current one.
+    async fn remove_new_data_file(new_path: &Path) -> Result<()> {
+        if new_path.exists() {
+            fs::remove_file(new_path)
+                .await
+                .map_err(Error::config_io(new_path))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns feed with the given name.
+    pub fn feed_source_mut(&mut self, name: &str) -> Result<&mut FeedSource> {
+        self.feeds
+            .iter_mut()
+            .find(|f| f.name == name)
+            .ok_or_else(|| Error::UnknownFeed(name.to_owned()))
+    }
+
+    /// Parses data file of the given feed with stop times of the given
+    /// stops only (see `Feed::for_stops`).
+    pub fn feed(&self, name: &str, stop_ids: &[&str]) -> Result<Feed> {
+        let source = self
+            .feeds
+            .iter()
+            .find(|f| f.name == name)
+            .ok_or_else(|| Error::UnknownFeed(name.to_owned()))?;
+
+        Feed::for_stops(&source.data_file_path, stop_ids)
+    }
 
-        // TODO: implement rayon
-        for (id, stop) in &output.stops {
-            // TODO: remove unwrap set up error.
-            let database = Database::from(&output.gtfs, stop).unwrap();
-            stops.push(Stop {
-                id: id.clone(),
-                name: stop.name.clone(),
-                database,
-            });
+    /// Builds up database for the given stop and adds it to config. The
+    /// feed needs stop times of the stop (see `feed`).
+    pub fn add_stop(
+        &mut self,
+        feed_name: &str,
+        feed: &Feed,
+        stop: &gtfs_structures::Stop,
+    ) -> Result<&Stop> {
+        if self
+            .stops
+            .iter()
+            .any(|s| s.feed == feed_name && s.id == stop.id)
+        {
+            return Err(Error::AlreadyExists(format!("Stop {}", stop.id)));
         }
 
-        stops
+        self.stops.push(Stop {
+            id: stop.id.clone(),
+            name: stop.name.clone(),
+            feed: feed_name.to_owned(),
+            platform: feed.platform_code(&stop.id).map(str::to_owned),
+            routes: vec![],
+            directions: vec![],
+            database: Database::from(feed, stop)?,
+        });
+
+        Ok(self.stops.last().unwrap())
+    }
+
+    /// Removes stop with the given ID (or name) from config.
+    pub fn remove_stop(&mut self, id_or_name: &str) -> Result<Stop> {
+        let i = self.stop_index(id_or_name)?;
+
+        Ok(self.stops.remove(i))
+    }
+
+    /// Returns stop with the given ID (or name).
+    pub fn stop_mut(&mut self, id_or_name: &str) -> Result<&mut Stop> {
+        let i = self.stop_index(id_or_name)?;
+
+        Ok(&mut self.stops[i])
+    }
+
+    /// Index of the only stop with the given ID (or name).
+    fn stop_index(&self, id_or_name: &str) -> Result<usize> {
+        let matching: Vec<usize> = self
+            .stops
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.id == id_or_name || s.name == id_or_name)
+            .map(|(i, _)| i)
+            .collect();
+
+        match matching.as_slice() {
+            [i] => Ok(*i),
+            [] => Err(Error::UnknownStop(id_or_name.to_owned())),
+            _ => Err(Error::AmbiguousStop(id_or_name.to_owned())),
+        }
+    }
+
+    /// Config directory path.
+    fn conf_dir() -> Result<PathBuf> {
+        let mut dir = dirs::config_dir().ok_or_else(|| {
+            Error::InvalidInput("No config directory available, set HOME".to_owned())
+        })?;
+        dir.push(CONF_DIR);
+
+        Ok(dir)
+    }
+
+    /// Path where data file of the given feed is stored.
+    pub fn data_file_path(feed_name: &str) -> Result<PathBuf> {
+        Ok(Self::conf_dir()?.join(format!("{}.gtfs", feed_name)))
+    }
+
+    /// Downloads or copies the data file into config folder. If the known
+    /// version is given the download is conditional and `None` is returned
+    /// when the server reports the data file has not been modified.
+    pub async fn download_or_copy_data_file(
+        data_file_path: &Path,
+        path_or_url: &str,
+        known_version: Option<&DataFileVersion>,
+    ) -> Result<Option<DataFileVersion>> {
+        let mut version = DataFileVersion::default();
+
+        if let Some(dir) = data_file_path.parent() {
+            fs::create_dir_all(dir)
+                .await
+                .map_err(Error::config_io(dir))?;
+        }
+
+        // Download or copy.
+        if path_or_url.starts_with("http") {
+            let mut request = reqwest::Client::new().get(path_or_url);
+
+            if let Some(known_version) = known_version {
+                if let Some(etag) = &known_version.etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+
+                if let Some(last_modified) = &known_version.last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+
+            let download = |source| Error::Download {
+                url: path_or_url.to_owned(),
+                source,
+            };
+            let response = request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(download)?;
+
+            if response.status() == StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            }
+
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+                    .map(|value| value.to_owned())
+            };
+            version.etag = header(ETAG);
+            version.last_modified = header(LAST_MODIFIED);
+
+            let data = response.bytes().await.map_err(download)?;
+            fs::write(&data_file_path, data)
+                .await
+                .map_err(Error::config_io(data_file_path))?;
+        } else {
+            fs::copy(path_or_url, &data_file_path)
+                .await
+                .map_err(|source| Error::DataFile {
+                    path: path_or_url.into(),
+                    source,
+                })?;
+        }
+
+        version.sha256 = Self::sha256(data_file_path).map_err(Error::config_io(data_file_path))?;
+
+        Ok(Some(version))
+    }
+
+    /// Computes SHA-256 hash (hex) of the given file.
+    fn sha256(path: &Path) -> std::io::Result<String> {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Saves config (serialize) to config YAML file and feed states and
+    /// stop databases next to the feed caches.
+    pub async fn save(&self) -> Result<()> {
+        let conf_dir = Self::conf_dir()?;
+        let conf_file_path = conf_dir.join(CONF_FILE);
+        let content = serde_yaml::to_string(self).map_err(|source| Error::ConfigSave {
+            path: conf_file_path.clone(),
+            source,
+        })?;
+
+        fs::create_dir_all(&conf_dir)
+            .await
+            .map_err(Error::config_io(&conf_dir))?;
+        fs::write(&conf_file_path, content)
+            .await
+            .map_err(Error::config_io(conf_file_path))?;
+
+        for feed in self.feeds.iter() {
+            Cache::save_state(feed).await?;
+            Cache::save(feed, &self.stops).await?;
+        }
+
+        Ok(())
     }
 }
@@ -1,10 +1,18 @@
-use chrono::NaiveDate;
-use gtfs_structures::{Gtfs, Stop};
+use chrono::{Datelike, NaiveDate, Weekday};
+use chrono_tz::Tz;
+use gtfs_structures::{Exception, Gtfs, PickupDropOffType, RouteType, Stop};
 use serde::{Deserialize, Serialize};
 
-// use crate::utils::is_stop_in_stops;
+use crate::error::{Error, Result};
+use crate::feed::{Feed, Frequency, Validity};
+use crate::timetables::Board;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Days a service runs on (calendar.txt together with calendar_dates.txt
+/// exceptions).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct CustomCalendar {
     pub monday: bool,
     pub tuesday: bool,
@@ -15,76 +23,452 @@ pub struct CustomCalendar {
     pub sunday: bool,
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
+    /// Extra days from calendar_dates.txt.
+    #[serde(default)]
+    pub added_dates: Vec<NaiveDate>,
+    /// Days the service doesn't run on from calendar_dates.txt.
+    #[serde(default)]
+    pub removed_dates: Vec<NaiveDate>,
 }
 
 impl From<&gtfs_structures::Calendar> for CustomCalendar {
     fn from(cal: &gtfs_structures::Calendar) -> Self {
         Self {
-            monday: cal.monday.clone(),
-            tuesday: cal.tuesday.clone(),
-            wednesday: cal.wednesday.clone(),
-            thursday: cal.thursday.clone(),
-            friday: cal.friday.clone(),
-            saturday: cal.saturday.clone(),
-            sunday: cal.sunday.clone(),
-            start_date: cal.start_date.clone(),
-            end_date: cal.end_date.clone(),
+            monday: cal.monday,
+            tuesday: cal.tuesday,
+            wednesday: cal.wednesday,
+            thursday: cal.thursday,
+            friday: cal.friday,
+            saturday: cal.saturday,
+            sunday: cal.sunday,
+            start_date: cal.start_date,
+            end_date: cal.end_date,
+            added_dates: vec![],
+            removed_dates: vec![],
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl CustomCalendar {
+    /// Builds calendar for the given service out of calendar.txt and
+    /// calendar_dates.txt. Services defined only thru calendar_dates.txt
+    /// get no week days and a date range spanning the added dates.
+    pub fn from_service(gtfs: &Gtfs, service_id: &str) -> Result<Self> {
+        let exceptions = gtfs.calendar_dates.get(service_id);
+
+        let mut calendar = match gtfs.calendar.get(service_id) {
+            Some(cal) => Self::from(cal),
+            None => {
+                let dates = exceptions
+                    .iter()
+                    .flat_map(|e| e.iter())
+                    .filter(|e| e.exception_type == Exception::Added)
+                    .map(|e| e.date);
+
+                match (dates.clone().min(), dates.max()) {
+                    (Some(start_date), Some(end_date)) => Self {
+                        monday: false,
+                        tuesday: false,
+                        wednesday: false,
+                        thursday: false,
+                        friday: false,
+                        saturday: false,
+                        sunday: false,
+                        start_date,
+                        end_date,
+                        added_dates: vec![],
+                        removed_dates: vec![],
+                    },
+                    _ => return Err(Error::MissingCalendar(service_id.to_owned())),
+                }
+            }
+        };
+
+        for exception in exceptions.iter().flat_map(|e| e.iter()) {
+            match exception.exception_type {
+                Exception::Added => calendar.added_dates.push(exception.date),
+                Exception::Deleted => calendar.removed_dates.push(exception.date),
+            }
+        }
+
+        Ok(calendar)
+    }
+
+    /// Tells whether the service runs on the given date. Exceptions
+    /// take precedence over week days and the date range.
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        if self.removed_dates.contains(&date) {
+            return false;
+        }
+
+        if self.added_dates.contains(&date) {
+            return true;
+        }
+
+        if date < self.start_date || date > self.end_date {
+            return false;
+        }
+
+        match date.weekday() {
+            Weekday::Mon => self.monday,
+            Weekday::Tue => self.tuesday,
+            Weekday::Wed => self.wednesday,
+            Weekday::Thu => self.thursday,
+            Weekday::Fri => self.friday,
+            Weekday::Sat => self.saturday,
+            Weekday::Sun => self.sunday,
+        }
+    }
+}
+
+/// Route together with the trip headsign - where passengers get from the
+/// stop by the route.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct RouteDirection {
+    /// Route short name.
+    pub route: String,
+    /// Trip headsign, `None` for trips without one.
+    pub headsign: Option<String>,
+}
+
+impl fmt::Display for RouteDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.headsign {
+            Some(headsign) => write!(f, "{} → {}", self.route, headsign),
+            None => write!(f, "{}", self.route),
+        }
+    }
+}
+
+/// Shared route name, ID, long name, color and agency ID of records.
+type RouteStrings = (Arc<str>, Arc<str>, Arc<str>, Option<Arc<str>>, Arc<str>);
+
+/// One trip stopping at the stop. Route strings are shared by all records
+/// of the route.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {
-    pub route: String, // human readable line name
+    /// Human readable line name (route short name).
+    pub route: Arc<str>,
+    #[serde(default)]
+    pub route_id: Arc<str>,
+    pub route_long_name: Arc<str>,
+    pub route_type: RouteType,
+    /// Hex RGB, e.g. "FF0000".
+    pub route_color: Option<Arc<str>>,
+    /// Agency operating the route.
+    #[serde(default)]
+    pub agency_id: Arc<str>,
+    /// Trip ID.
     pub trip: String,
-    pub calendar: CustomCalendar,
-    pub stop_time: Option<u32>,
+    pub headsign: Option<String>,
+    /// Trip direction_id (0 or 1).
+    pub direction: Option<u8>,
+    /// Shared by all records of the same service.
+    pub calendar: Arc<CustomCalendar>,
+    /// Seconds since service day start, may exceed 24 hours.
+    pub arrival_time: Option<u32>,
+    /// Seconds since service day start, may exceed 24 hours.
+    pub departure_time: Option<u32>,
+    pub pickup_type: PickupDropOffType,
+    pub drop_off_type: PickupDropOffType,
+    /// Trip starts here.
+    pub first_stop: bool,
+    /// Trip terminates here.
+    pub last_stop: bool,
+    /// Stop name.
     pub stop: String,
+    /// Stop ID, a platform for stations.
+    #[serde(default)]
+    pub stop_id: String,
+    #[serde(default)]
+    pub stop_sequence: u16,
+    /// Position of the stop within the trip (0 for the first stop). Stop
+    /// sequences only increase, they don't have to be consecutive.
+    #[serde(default)]
+    pub position: u16,
+    /// Platform code of the stop (a platform of the configured station).
+    #[serde(default)]
+    pub platform: Option<String>,
+    /// Headway (in seconds) of trips that don't run exactly on schedule.
+    pub headway: Option<u32>,
+    /// Start time (seconds since service day start) of a trip expanded
+    /// from frequencies.txt, which tells apart trips sharing the trip ID.
+    #[serde(default)]
+    pub trip_start: Option<u32>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Record {
+    /// Route and headsign of the record trip.
+    pub fn route_direction(&self) -> RouteDirection {
+        RouteDirection {
+            route: self.route.to_string(),
+            headsign: self.headsign.clone(),
+        }
+    }
+
+    /// Returns stop time relevant for the given board. If it's missing
+    /// the other one is used.
+    pub fn stop_time(&self, board: Board) -> Option<u32> {
+        match board {
+            Board::Departures => self.departure_time.or(self.arrival_time),
+            Board::Arrivals => self.arrival_time.or(self.departure_time),
+        }
+    }
+
+    /// Tells whether passengers can get on (departures) or get off
+    /// (arrivals) at this stop.
+    pub fn serves(&self, board: Board) -> bool {
+        match board {
+            Board::Departures => {
+                !self.last_stop && self.pickup_type != PickupDropOffType::NotAvailable
+            }
+            Board::Arrivals => {
+                !self.first_stop && self.drop_off_type != PickupDropOffType::NotAvailable
+            }
+        }
+    }
+}
+
+/// All stop times of one stop, so departures can be looked up without
+/// parsing the data file again.
+#[derive(Default, Serialize, Deserialize)]
 pub struct Database {
     // TODO: vec -> array
+    /// Stop times of all trips calling at the stop.
     pub records: Vec<Record>,
+    /// Agency timezone (IANA name) all stop times are relative to.
+    pub timezone: String,
+    /// Service period of the feed the database was built from.
+    #[serde(default)]
+    pub validity: Option<Validity>,
+    /// Trip ID -> stop sequences of all trip stop times (ascending) of
+    /// the record trips, so any stop sequence can be told its position.
+    #[serde(default)]
+    pub stop_sequences: HashMap<String, Vec<u16>>,
 }
 
 impl<'a> Database {
-    pub fn from(gtfs: &'a Gtfs, stop: &Stop) -> Result<Self, Box<dyn std::error::Error>> {
-        let records = Self::fetch(gtfs, stop)?;
+    /// Builds database of the given stop from the feed. Database of a
+    /// station merges all its platforms.
+    pub fn from(feed: &'a Feed, stop: &Stop) -> Result<Self> {
+        let gtfs = &feed.gtfs;
+        let mut records = Self::fetch(feed, &stop.id)?;
+
+        if Feed::is_station(stop) {
+            for platform in feed.platforms(&stop.id) {
+                records.extend(Self::fetch(feed, &platform.id)?);
+            }
+        }
         // Self::debug(routes_and_calendars);
 
-        Ok(Self { records })
+        let stop_sequences = records
+            .iter()
+            .filter_map(|r| Some((r.trip.clone(), feed.stop_sequences.get(&r.trip)?.clone())))
+            .collect();
+
+        // All agencies within one feed share the same timezone.
+        let timezone = gtfs
+            .agencies
+            .first()
+            .map(|agency| agency.timezone.clone())
+            .ok_or_else(|| Error::InvalidFeed("no agency found".to_owned()))?;
+        timezone
+            .parse::<Tz>()
+            .map_err(|_| Error::InvalidFeed(format!("unknown agency timezone {}", timezone)))?;
+
+        Ok(Self {
+            records,
+            timezone,
+            validity: feed.validity(),
+            stop_sequences,
+        })
+    }
+
+    /// Position of the stop time with the given sequence within the trip.
+    /// Returns `None` if the trip has no such stop time.
+    pub fn stop_position(&self, trip: &str, stop_sequence: u16) -> Option<u16> {
+        let sequences = self.stop_sequences.get(trip)?;
+
+        sequences
+            .binary_search(&stop_sequence)
+            .ok()
+            .map(|i| i as u16)
+    }
+
+    /// All route directions passengers can board at the stop, ordered by
+    /// route.
+    pub fn directions(&self) -> BTreeSet<RouteDirection> {
+        self.records
+            .iter()
+            .filter(|r| r.serves(Board::Departures))
+            .map(Record::route_direction)
+            .collect()
+    }
+
+    /// Parsed agency timezone.
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    /// Service period of the database. If the feed one is not known it's
+    /// computed from record calendars.
+    pub fn service_period(&self) -> Option<Validity> {
+        if self.validity.is_some() {
+            return self.validity;
+        }
+
+        let calendars = self.records.iter().map(|r| &r.calendar);
+        let starts = calendars
+            .clone()
+            .flat_map(|c| c.added_dates.iter().chain(std::iter::once(&c.start_date)));
+        let ends = calendars.flat_map(|c| c.added_dates.iter().chain(std::iter::once(&c.end_date)));
+
+        Some(Validity {
+            start: *starts.min()?,
+            end: *ends.max()?,
+        })
     }
 
-    /// Walks thru all stops and collects all trips that intersect any
-    /// of selected stop.
-    fn fetch(gtfs: &'a Gtfs, stop: &Stop) -> Result<Vec<Record>, Box<dyn std::error::Error>> {
+    /// Collects all trips stopping at the stop (looked up in the feed
+    /// stop index).
+    fn fetch(feed: &'a Feed, stop_id: &str) -> Result<Vec<Record>> {
+        let gtfs = &feed.gtfs;
         let mut records = vec![];
+        // Service ID -> calendar, `None` for services that never run.
+        let mut calendars: HashMap<&str, Option<Arc<CustomCalendar>>> = HashMap::new();
+        // Route ID -> name, ID, long name, color and agency ID.
+        let mut routes: HashMap<&str, RouteStrings> = HashMap::new();
+
+        for stop_time in feed.stop_times(stop_id) {
+            let trip = match gtfs.trips.get(&stop_time.trip_id) {
+                Some(trip) => trip,
+                None => continue,
+            };
+            let route = match gtfs.routes.get(&trip.route_id) {
+                Some(route) => route,
+                None => continue,
+            };
+            let (i, time) = (stop_time.index, &trip.stop_times[stop_time.index]);
+
+            // Build calendar only once per service. Services with just
+            // removed dates have no days to run on, so their trips are
+            // left out rather than failing the whole stop.
+            let calendar = match calendars.get(trip.service_id.as_str()) {
+                Some(calendar) => calendar.clone(),
+                None => {
+                    let calendar = match CustomCalendar::from_service(gtfs, &trip.service_id) {
+                        Ok(calendar) => Some(Arc::new(calendar)),
+                        Err(Error::MissingCalendar(_)) => None,
+                        Err(err) => return Err(err),
+                    };
+                    calendars.insert(trip.service_id.as_str(), calendar.clone());
 
-        // TODO: rayon optimalization
-        for (_, route) in &gtfs.routes {
-            for (_, trip) in gtfs.trips.iter().filter(|trip| trip.1.route_id == route.id) {
-                for time in &trip.stop_times {
-                    if time.stop.id == stop.id {
-                        // if is_stop_in_stops(&time.stop, stops) {
-                        records.push(Record {
-                            // route: route.long_name.clone(),
-                            route: route.short_name.clone(),
-                            trip: trip.service_id.clone(),
-                            calendar: CustomCalendar::from(
-                                gtfs.get_calendar(trip.service_id.as_str())?,
-                            ),
-                            stop_time: time.arrival_time.clone(),
-                            stop: time.stop.name.clone(),
-                        });
-                    }
+                    calendar
                 }
+            };
+            let calendar = match calendar {
+                Some(calendar) => calendar,
+                None => continue,
+            };
+
+            let (name, id, long_name, color, agency_id) =
+                routes.entry(route.id.as_str()).or_insert_with(|| {
+                    (
+                        route.short_name.as_str().into(),
+                        route.id.as_str().into(),
+                        route.long_name.as_str().into(),
+                        route
+                            .route_color
+                            .map(|c| format!("{:02X}{:02X}{:02X}", c.r, c.g, c.b).into()),
+                        // Agency may be left out if the feed has just one.
+                        route
+                            .agency_id
+                            .as_ref()
+                            .or_else(|| gtfs.agencies.first()?.id.as_ref())
+                            .map_or("", String::as_str)
+                            .into(),
+                    )
+                });
+
+            let record = Record {
+                route: name.clone(),
+                route_id: id.clone(),
+                route_long_name: long_name.clone(),
+                route_type: route.route_type,
+                route_color: color.clone(),
+                agency_id: agency_id.clone(),
+                trip: trip.id.clone(),
+                headsign: trip.trip_headsign.clone(),
+                direction: feed.directions.get(&trip.id).copied(),
+                calendar,
+                arrival_time: time.arrival_time,
+                departure_time: time.departure_time,
+                pickup_type: time.pickup_type.unwrap_or_default(),
+                drop_off_type: time.drop_off_type.unwrap_or_default(),
+                first_stop: i == 0,
+                last_stop: i == trip.stop_times.len() - 1,
+                stop: time.stop.name.clone(),
+                stop_id: time.stop.id.clone(),
+                stop_sequence: time.stop_sequence,
+                position: feed
+                    .stop_sequences
+                    .get(&trip.id)
+                    .map_or(i, |s| s.partition_point(|&s| s < time.stop_sequence))
+                    as u16,
+                platform: feed.platform_code(&time.stop.id).map(str::to_owned),
+                headway: None,
+                trip_start: None,
+            };
+
+            // Headway based trips are just templates for the real ones.
+            match feed.frequencies.get(&trip.id) {
+                Some(frequencies) => {
+                    let first = &trip.stop_times[0];
+                    let trip_start = first.departure_time.or(first.arrival_time);
+
+                    records.extend(Self::expand_frequencies(
+                        &record,
+                        frequencies,
+                        trip_start.unwrap_or(0),
+                    ));
+                }
+                None => records.push(record),
             }
         }
 
         Ok(records)
     }
 
+    /// Expands template record of a headway based trip into records of
+    /// all trips given by frequencies. Template stop times are shifted by
+    /// the difference between each trip start and the template trip start.
+    fn expand_frequencies(
+        record: &Record,
+        frequencies: &[Frequency],
+        trip_start: u32,
+    ) -> Vec<Record> {
+        let shift =
+            |time: Option<u32>, start: u32| time.and_then(|t| (t + start).checked_sub(trip_start));
+        let mut records = vec![];
+
+        for frequency in frequencies {
+            for start in frequency.start_times() {
+                let mut expanded = record.clone();
+                expanded.arrival_time = shift(record.arrival_time, start);
+                expanded.departure_time = shift(record.departure_time, start);
+                expanded.trip_start = Some(start);
+
+                if !frequency.is_exact() {
+                    expanded.headway = Some(frequency.headway_secs);
+                }
+
+                records.push(expanded);
+            }
+        }
+
+        records
+    }
+
     // fn debug(found_routes: Vec<(&Route, &Calendar)>) {
     //     let uniq_routes_with_cals = found_routes
     //         .into_iter()
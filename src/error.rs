@@ -0,0 +1,161 @@
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong in transpors. Each variant has its own
+/// process exit code, see `exit_code`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Invalid command line or request input.
+    #[error("{0}")]
+    InvalidInput(String),
+
+    /// Config file doesn't exist yet.
+    #[error("No config found, run \"transpors init\" first")]
+    NoConfig,
+
+    /// Config directory or file (including data files stored there)
+    /// can't be read or written.
+    #[error("Can't access {}: {source}, check its permissions", path.display())]
+    ConfigIo {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Config file content can't be parsed.
+    #[error("Config file {} is invalid: {source}, fix it or remove it and run \"transpors init\"", path.display())]
+    ConfigParse {
+        path: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+
+    /// Data file download failed.
+    #[error("Can't download {url}: {source}, check the URL and your connection")]
+    Download {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// Local data file to copy can't be read.
+    #[error("Can't read data file {}: {source}", path.display())]
+    DataFile {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Data file isn't a valid GTFS archive.
+    #[error("Can't parse GTFS data file {}: {message}", path.display())]
+    GtfsParse { path: PathBuf, message: String },
+
+    /// Data file parses but its content can't be used.
+    #[error("Invalid GTFS data: {0}")]
+    InvalidFeed(String),
+
+    /// Service has neither a calendar.txt row nor any added date.
+    #[error("No calendar found for service {0}, the data file is incomplete")]
+    MissingCalendar(String),
+
+    /// No stop matches the given stop ID or name.
+    #[error("No stop {0} found, check the name or use the stop ID")]
+    UnknownStop(String),
+
+    /// More stops match the given name equally well.
+    #[error("Stop {0} is ambiguous, use the stop ID (or --feed) instead")]
+    AmbiguousStop(String),
+
+    /// No feed of the given name is configured.
+    #[error("No feed {0} in config, see \"transpors feed list\"")]
+    UnknownFeed(String),
+
+    /// Feed or stop being added is already in config.
+    #[error("{0} is already in config")]
+    AlreadyExists(String),
+
+    /// Realtime data can't be fetched or decoded.
+    #[error("Can't fetch realtime data from {url}: {message}")]
+    Realtime { url: String, message: String },
+
+    /// Structured output can't be written.
+    #[error("Can't write output: {0}")]
+    Output(String),
+
+    /// Terminal (full screen board) failure.
+    #[error("Terminal error: {0}")]
+    Terminal(String),
+
+    /// HTTP server can't be started or fails.
+    #[error("Server error: {0}")]
+    Server(String),
+
+    /// Reading user input or writing to stdout failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// Cache of stop databases can't be encoded.
+    #[error("Can't write cache {}: {message}", path.display())]
+    Cache { path: PathBuf, message: String },
+
+    /// Config can't be serialized to be saved (the file is left as it
+    /// was).
+    #[error("Can't save config {}: {source}", path.display())]
+    ConfigSave {
+        path: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+}
+
+impl Error {
+    /// Process exit code (1 is left for unexpected failures).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidInput(_) => 2,
+            Error::NoConfig => 3,
+            Error::ConfigIo { .. } => 4,
+            Error::ConfigParse { .. } => 5,
+            Error::Download { .. } => 6,
+            Error::DataFile { .. } => 7,
+            Error::GtfsParse { .. } => 8,
+            Error::InvalidFeed(_) => 9,
+            Error::MissingCalendar(_) => 10,
+            Error::UnknownStop(_) => 11,
+            Error::AmbiguousStop(_) => 12,
+            Error::UnknownFeed(_) => 13,
+            Error::AlreadyExists(_) => 14,
+            Error::Realtime { .. } => 15,
+            Error::Output(_) => 16,
+            Error::Terminal(_) => 17,
+            Error::Server(_) => 18,
+            Error::Io(_) => 19,
+            Error::Cache { .. } => 20,
+            Error::ConfigSave { .. } => 21,
+        }
+    }
+
+    /// Config IO error of the given path.
+    pub(crate) fn config_io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+
+        move |source| Error::ConfigIo { path, source }
+    }
+}
+
+/// Result with transpors error.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Error message including all its sources (some libraries keep the
+/// details in sources only).
+pub(crate) fn describe(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+
+    while let Some(err) = source {
+        message.push_str(&format!(": {}", err));
+        source = err.source();
+    }
+
+    message
+}
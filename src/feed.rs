@@ -0,0 +1,633 @@
+use chrono::NaiveDate;
+use rayon::prelude::*;
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use gtfs_structures::{
+    Agency, Calendar, CalendarDate, Exception, FeedInfo, Gtfs, LocationType, RawStopTime, RawTrip,
+    Route, Stop, StopTime, Trip,
+};
+
+use crate::error::{describe, Error, Result};
+use crate::search::{self, Score};
+
+/// Trip columns gtfs-structures doesn't parse.
+#[derive(Deserialize)]
+struct TripExtras {
+    trip_id: String,
+    direction_id: Option<u8>,
+}
+
+/// Stop columns gtfs-structures doesn't parse.
+#[derive(Deserialize)]
+struct StopExtras {
+    stop_id: String,
+    platform_code: Option<String>,
+}
+
+/// Headway based service of a trip (frequencies.txt row).
+#[derive(Deserialize)]
+pub struct Frequency {
+    /// Template trip, its stop times are shifted to each trip start.
+    pub trip_id: String,
+    /// First trip start (seconds since service day start).
+    #[serde(deserialize_with = "deserialize_time")]
+    pub start_time: u32,
+    /// End of the window, no trip starts at or after it.
+    #[serde(deserialize_with = "deserialize_time")]
+    pub end_time: u32,
+    /// Seconds between trip starts.
+    pub headway_secs: u32,
+    /// 1 when trips run exactly on schedule, otherwise only headway
+    /// is kept.
+    pub exact_times: Option<u8>,
+}
+
+impl Frequency {
+    /// Start times (seconds since service day start) of all trips
+    /// within this frequency window.
+    pub fn start_times(&self) -> impl Iterator<Item = u32> {
+        (self.start_time..self.end_time).step_by(self.headway_secs.max(1) as usize)
+    }
+
+    /// Whether trips start exactly at the computed start times, so they
+    /// can be shown as scheduled departures rather than "every N min".
+    pub fn is_exact(&self) -> bool {
+        self.exact_times == Some(1)
+    }
+}
+
+/// Period the feed provides service for.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Validity {
+    /// First day of service.
+    pub start: NaiveDate,
+    /// Last day of service (inclusive).
+    pub end: NaiveDate,
+}
+
+impl fmt::Display for Validity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} - {}", self.start, self.end)
+    }
+}
+
+/// Stop time of a trip - trip ID and position within the trip stop
+/// times.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StopTimeRef {
+    pub trip_id: String,
+    pub index: usize,
+}
+
+/// GTFS data file together with the bits gtfs-structures doesn't
+/// parse (read directly from the data file).
+pub struct Feed {
+    /// Parsed data file (just some stop times when built by
+    /// `for_stops`).
+    pub gtfs: Gtfs,
+    /// Trip ID -> direction_id.
+    pub directions: HashMap<String, u8>,
+    /// Trip ID -> frequencies (template trips only).
+    pub frequencies: HashMap<String, Vec<Frequency>>,
+    /// Stop ID -> platform_code (platforms having one only).
+    pub platform_codes: HashMap<String, String>,
+    /// Trip ID -> stop sequences of all trip stop times (ascending), even
+    /// of those `for_stops` doesn't keep.
+    pub stop_sequences: HashMap<String, Vec<u16>>,
+    /// Stop ID -> stop times at the stop, built on first use.
+    stop_index: OnceLock<HashMap<String, Vec<StopTimeRef>>>,
+}
+
+impl Feed {
+    /// Parses data file (zip archive or directory).
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let parse_error = |message| Error::GtfsParse {
+            path: path.to_owned(),
+            message,
+        };
+        let gtfs = Gtfs::from_path(
+            path.to_str()
+                .ok_or_else(|| parse_error("path isn't valid UTF-8".to_owned()))?,
+        )
+        .map_err(|err| parse_error(describe(&err)))?;
+
+        let directions = read_objs::<TripExtras>(path, "trips.txt")?
+            .into_iter()
+            .filter_map(|t| t.direction_id.map(|direction| (t.trip_id, direction)))
+            .collect();
+
+        let mut frequencies: HashMap<String, Vec<Frequency>> = HashMap::new();
+
+        for frequency in read_objs::<Frequency>(path, "frequencies.txt")? {
+            frequencies
+                .entry(frequency.trip_id.clone())
+                .or_default()
+                .push(frequency);
+        }
+
+        let stop_sequences = gtfs
+            .trips
+            .values()
+            .map(|t| {
+                let sequences = t.stop_times.iter().map(|s| s.stop_sequence).collect();
+                (t.id.clone(), sequences)
+            })
+            .collect();
+
+        Ok(Self {
+            gtfs,
+            directions,
+            frequencies,
+            platform_codes: read_platform_codes(path)?,
+            stop_sequences,
+            stop_index: OnceLock::new(),
+        })
+    }
+
+    /// Parses data file keeping only trips calling at the given stops and
+    /// only their stop times at the stops and at the trip ends. Stop times
+    /// are streamed (twice), so memory use doesn't grow with the
+    /// stop_times.txt size like with `from_path`. Stop databases built
+    /// from it are the same. Stations include their platforms. With no
+    /// stops given stop times aren't read at all.
+    pub fn for_stops(path: &Path, stop_ids: &[&str]) -> Result<Self> {
+        let stops: HashMap<String, Arc<Stop>> = read_objs::<Stop>(path, "stops.txt")?
+            .into_iter()
+            .map(|s| (s.id.clone(), Arc::new(s)))
+            .collect();
+        let mut wanted: HashSet<&str> = stop_ids.iter().copied().collect();
+        wanted.extend(
+            stops
+                .values()
+                .filter(|s| {
+                    s.parent_station
+                        .as_ref()
+                        .is_some_and(|parent| stop_ids.contains(&parent.as_str()))
+                })
+                .map(|s| s.id.as_str()),
+        );
+
+        // First pass finds trips calling at the stops.
+        let mut trip_ids = HashSet::new();
+
+        if !wanted.is_empty() {
+            for_each_obj(path, "stop_times.txt", |time: RawStopTime| {
+                if wanted.contains(time.stop_id.as_str()) {
+                    trip_ids.insert(time.trip_id);
+                }
+
+                Ok(())
+            })?;
+        }
+
+        let mut trips: HashMap<String, Trip> = HashMap::new();
+
+        for_each_obj(path, "trips.txt", |trip: RawTrip| {
+            if trip_ids.contains(&trip.id) {
+                trips.insert(
+                    trip.id.clone(),
+                    Trip {
+                        id: trip.id,
+                        service_id: trip.service_id,
+                        route_id: trip.route_id,
+                        stop_times: vec![],
+                        shape_id: trip.shape_id,
+                        trip_headsign: trip.trip_headsign,
+                    },
+                );
+            }
+
+            Ok(())
+        })?;
+
+        // Second pass keeps their stop times at the stops and the first
+        // and the last one of each trip (trips may be in any order), and
+        // sequences of all of them.
+        let mut ends: HashMap<String, (StopTime, StopTime)> = HashMap::new();
+        let mut stop_sequences: HashMap<String, Vec<u16>> = HashMap::new();
+
+        if !trips.is_empty() {
+            for_each_obj(path, "stop_times.txt", |raw: RawStopTime| {
+                let trip = match trips.get_mut(&raw.trip_id) {
+                    Some(trip) => trip,
+                    None => return Ok(()),
+                };
+                let stop = stops.get(&raw.stop_id).ok_or_else(|| Error::GtfsParse {
+                    path: path.to_owned(),
+                    message: format!("stop_times.txt: unknown stop {}", raw.stop_id),
+                })?;
+
+                if wanted.contains(raw.stop_id.as_str()) {
+                    trip.stop_times.push(StopTime::from(&raw, stop.clone()));
+                }
+
+                stop_sequences
+                    .entry(raw.trip_id.clone())
+                    .or_default()
+                    .push(raw.stop_sequence);
+
+                match ends.get_mut(&raw.trip_id) {
+                    Some((first, last)) => {
+                        if raw.stop_sequence < first.stop_sequence {
+                            *first = StopTime::from(&raw, stop.clone());
+                        }
+
+                        if raw.stop_sequence > last.stop_sequence {
+                            *last = StopTime::from(&raw, stop.clone());
+                        }
+                    }
+                    None => {
+                        let ends_at = (
+                            StopTime::from(&raw, stop.clone()),
+                            StopTime::from(&raw, stop.clone()),
+                        );
+                        ends.insert(raw.trip_id, ends_at);
+                    }
+                }
+
+                Ok(())
+            })?;
+        }
+
+        for (trip_id, (first, last)) in ends {
+            if let Some(trip) = trips.get_mut(&trip_id) {
+                trip.stop_times.push(first);
+                trip.stop_times.push(last);
+                trip.stop_times.sort_by_key(|t| t.stop_sequence);
+                trip.stop_times.dedup_by_key(|t| t.stop_sequence);
+            }
+        }
+
+        for sequences in stop_sequences.values_mut() {
+            sequences.sort_unstable();
+        }
+
+        let mut calendar_dates: HashMap<String, Vec<CalendarDate>> = HashMap::new();
+
+        for date in read_objs::<CalendarDate>(path, "calendar_dates.txt")? {
+            calendar_dates
+                .entry(date.service_id.clone())
+                .or_default()
+                .push(date);
+        }
+
+        let gtfs = Gtfs {
+            calendar: read_objs::<Calendar>(path, "calendar.txt")?
+                .into_iter()
+                .map(|c| (c.id.clone(), c))
+                .collect(),
+            calendar_dates,
+            stops,
+            routes: read_objs::<Route>(path, "routes.txt")?
+                .into_iter()
+                .map(|r| (r.id.clone(), r))
+                .collect(),
+            trips,
+            agencies: read_objs::<Agency>(path, "agency.txt")?,
+            feed_info: read_objs::<FeedInfo>(path, "feed_info.txt")?,
+            ..Gtfs::default()
+        };
+
+        let mut directions = HashMap::new();
+
+        for_each_obj(path, "trips.txt", |trip: TripExtras| {
+            if let (true, Some(direction)) = (trip_ids.contains(&trip.trip_id), trip.direction_id) {
+                directions.insert(trip.trip_id, direction);
+            }
+
+            Ok(())
+        })?;
+
+        let mut frequencies: HashMap<String, Vec<Frequency>> = HashMap::new();
+
+        for_each_obj(path, "frequencies.txt", |frequency: Frequency| {
+            if trip_ids.contains(&frequency.trip_id) {
+                frequencies
+                    .entry(frequency.trip_id.clone())
+                    .or_default()
+                    .push(frequency);
+            }
+
+            Ok(())
+        })?;
+
+        Ok(Self {
+            gtfs,
+            directions,
+            frequencies,
+            platform_codes: read_platform_codes(path)?,
+            stop_sequences,
+            stop_index: OnceLock::new(),
+        })
+    }
+
+    /// Tells whether the stop is a station grouping platforms.
+    pub fn is_station(stop: &Stop) -> bool {
+        stop.location_type == LocationType::StopArea
+    }
+
+    /// Platforms (child stops) of the given station ordered by platform
+    /// code.
+    pub fn platforms(&self, station_id: &str) -> Vec<Arc<Stop>> {
+        let mut platforms: Vec<Arc<Stop>> = self
+            .gtfs
+            .stops
+            .values()
+            .filter(|s| s.parent_station.as_deref() == Some(station_id))
+            .filter(|s| s.location_type == LocationType::StopPoint)
+            .cloned()
+            .collect();
+
+        platforms.sort_by(|a, b| {
+            self.platform_code(&a.id)
+                .cmp(&self.platform_code(&b.id))
+                .then(a.id.cmp(&b.id))
+        });
+
+        platforms
+    }
+
+    /// Platform code of the given stop (if it has one).
+    pub fn platform_code(&self, stop_id: &str) -> Option<&str> {
+        self.platform_codes.get(stop_id).map(String::as_str)
+    }
+
+    /// Groups found stops for selection - platforms are listed under
+    /// their station (all of them, even those not found). Groups are in
+    /// the order their first stop was found.
+    pub fn group_by_station(&self, found: &[Arc<Stop>]) -> Vec<(Arc<Stop>, Vec<Arc<Stop>>)> {
+        let mut groups: Vec<(Arc<Stop>, Vec<Arc<Stop>>)> = vec![];
+
+        for stop in found {
+            let station = stop
+                .parent_station
+                .as_ref()
+                .and_then(|parent| self.gtfs.stops.get(parent))
+                .unwrap_or(stop);
+
+            if groups.iter().any(|(s, _)| s.id == station.id) {
+                continue;
+            }
+
+            let platforms = if Self::is_station(station) {
+                self.platforms(&station.id)
+            } else {
+                vec![]
+            };
+
+            groups.push((station.clone(), platforms));
+        }
+
+        groups
+    }
+
+    /// Stop times at the given stop, ordered by trip ID and position.
+    pub fn stop_times(&self, stop_id: &str) -> &[StopTimeRef] {
+        self.stop_index
+            .get_or_init(|| Self::build_stop_index(&self.gtfs))
+            .get(stop_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Indexes stop times of all trips by stop (in parallel), so stops
+    /// don't have to scan all trips.
+    fn build_stop_index(gtfs: &Gtfs) -> HashMap<String, Vec<StopTimeRef>> {
+        let mut index = gtfs
+            .trips
+            .par_iter()
+            .fold(HashMap::new, |mut index, (trip_id, trip)| {
+                for (i, time) in trip.stop_times.iter().enumerate() {
+                    index
+                        .entry(time.stop.id.clone())
+                        .or_insert_with(Vec::new)
+                        .push(StopTimeRef {
+                            trip_id: trip_id.clone(),
+                            index: i,
+                        });
+                }
+
+                index
+            })
+            .reduce(HashMap::new, |mut index, other| {
+                for (stop_id, mut stop_times) in other {
+                    index
+                        .entry(stop_id)
+                        .or_insert_with(Vec::new)
+                        .append(&mut stop_times);
+                }
+
+                index
+            });
+
+        // Keep the order stable regardless of how the work was split.
+        index
+            .par_iter_mut()
+            .for_each(|(_, stop_times)| stop_times.sort_unstable());
+
+        index
+    }
+
+    /// Human readable names of all routes.
+    pub fn route_names(&self) -> BTreeSet<String> {
+        self.gtfs
+            .routes
+            .values()
+            .map(|r| {
+                if r.short_name.is_empty() {
+                    r.long_name.clone()
+                } else {
+                    r.short_name.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// Service period of the feed - taken from feed_info.txt or (if
+    /// missing) computed from calendars.
+    pub fn validity(&self) -> Option<Validity> {
+        let feed_info = self.gtfs.feed_info.first();
+
+        if let Some((Some(start), Some(end))) = feed_info.map(|i| (i.start_date, i.end_date)) {
+            return Some(Validity { start, end });
+        }
+
+        let calendar_dates = self
+            .gtfs
+            .calendar
+            .values()
+            .flat_map(|c| vec![c.start_date, c.end_date]);
+        let exception_dates = self
+            .gtfs
+            .calendar_dates
+            .values()
+            .flatten()
+            .filter(|d| d.exception_type == Exception::Added)
+            .map(|d| d.date);
+        let dates: Vec<NaiveDate> = calendar_dates.chain(exception_dates).collect();
+
+        Some(Validity {
+            start: *dates.iter().min()?,
+            end: *dates.iter().max()?,
+        })
+    }
+
+    /// Finds stops by stop ID, stop code or name. Direct (ID, code) and
+    /// exact name matches win over stops just resembling the name.
+    pub fn find_stops(&self, id_or_name: &str) -> Vec<Arc<Stop>> {
+        let found = self.search_stops(id_or_name);
+        let best = match found.first() {
+            Some((_, score)) => *score,
+            None => return vec![],
+        };
+
+        let found: Vec<Arc<Stop>> = found
+            .into_iter()
+            .take_while(|(_, score)| {
+                if best.is_direct() {
+                    *score == best
+                } else if best.is_exact() {
+                    score.is_exact()
+                } else {
+                    true
+                }
+            })
+            .map(|(stop, _)| stop)
+            .collect();
+
+        // Platforms of a found station are just its parts.
+        let stations: HashSet<&str> = found
+            .iter()
+            .filter(|s| Self::is_station(s))
+            .map(|s| s.id.as_str())
+            .collect();
+
+        found
+            .iter()
+            .filter(|s| {
+                !s.parent_station
+                    .as_ref()
+                    .is_some_and(|parent| stations.contains(parent.as_str()))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Searches stops by name ignoring case and diacritics and tolerating
+    /// typos, or by stop ID or code. Returns matching stops by relevance.
+    pub fn search_stops(&self, query: &str) -> Vec<(Arc<Stop>, Score)> {
+        let query = search::Query::new(query);
+        let mut found: Vec<(Arc<Stop>, Score)> = self
+            .gtfs
+            .stops
+            .values()
+            .filter_map(|stop| query.score(stop).map(|score| (stop.clone(), score)))
+            .collect();
+
+        found.sort_by(|(a, a_score), (b, b_score)| {
+            b_score
+                .cmp(a_score)
+                .then(a.name.cmp(&b.name))
+                .then(a.id.cmp(&b.id))
+        });
+
+        found
+    }
+}
+
+/// Reads platform codes of all stops having one.
+fn read_platform_codes(path: &Path) -> Result<HashMap<String, String>> {
+    let mut platform_codes = HashMap::new();
+
+    for_each_obj(path, "stops.txt", |stop: StopExtras| {
+        if let Some(code) = stop.platform_code.filter(|c| !c.is_empty()) {
+            platform_codes.insert(stop.stop_id, code);
+        }
+
+        Ok(())
+    })?;
+
+    Ok(platform_codes)
+}
+
+/// Reads all records of the given file from data file (zip archive or
+/// directory). Missing file results in no records.
+fn read_objs<T: DeserializeOwned>(path: &Path, file_name: &str) -> Result<Vec<T>> {
+    let mut objs = vec![];
+
+    for_each_obj(path, file_name, |obj| {
+        objs.push(obj);
+        Ok(())
+    })?;
+
+    Ok(objs)
+}
+
+/// Streams records of the given file from data file (zip archive or
+/// directory) one by one. Missing file results in no records.
+fn for_each_obj<T: DeserializeOwned>(
+    path: &Path,
+    file_name: &str,
+    f: impl FnMut(T) -> Result<()>,
+) -> Result<()> {
+    let parse_error = |err: &dyn std::error::Error| Error::GtfsParse {
+        path: path.to_owned(),
+        message: format!("{}: {}", file_name, describe(err)),
+    };
+
+    if path.is_dir() {
+        let file_path = path.join(file_name);
+
+        if !file_path.exists() {
+            return Ok(());
+        }
+
+        let file = File::open(file_path).map_err(|err| parse_error(&err))?;
+
+        return deserialize(file, f, parse_error);
+    }
+
+    let file = File::open(path).map_err(|err| parse_error(&err))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|err| parse_error(&err))?;
+
+    // Files may be nested in a folder within the archive.
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).map_err(|err| parse_error(&err))?;
+
+        if file.name() == file_name || file.name().ends_with(&format!("/{}", file_name)) {
+            return deserialize(file, f, parse_error);
+        }
+    }
+
+    Ok(())
+}
+
+fn deserialize<T: DeserializeOwned, R: Read>(
+    reader: R,
+    mut f: impl FnMut(T) -> Result<()>,
+    parse_error: impl Fn(&dyn std::error::Error) -> Error,
+) -> Result<()> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::Fields)
+        .from_reader(reader);
+
+    for obj in reader.deserialize() {
+        f(obj.map_err(|err| parse_error(&err))?)?;
+    }
+
+    Ok(())
+}
+
+fn deserialize_time<'de, D: Deserializer<'de>>(de: D) -> std::result::Result<u32, D::Error> {
+    let s = String::deserialize(de)?;
+
+    gtfs_structures::parse_time(&s).map_err(de::Error::custom)
+}
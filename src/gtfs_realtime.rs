@@ -0,0 +1,213 @@
+//! Subset of GTFS Realtime protocol buffer messages
+//! (<https://gtfs.org/realtime/proto/>) used by transpors.
+//! Fields not listed here are skipped when decoding, the listed ones
+//! have names and meanings given by the specification.
+
+/// Content of one realtime feed fetch.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FeedMessage {
+    #[prost(message, required, tag = "1")]
+    pub header: FeedHeader,
+    #[prost(message, repeated, tag = "2")]
+    pub entity: Vec<FeedEntity>,
+}
+
+/// Metadata of the feed message.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FeedHeader {
+    #[prost(string, required, tag = "1")]
+    pub gtfs_realtime_version: String,
+    #[prost(uint64, optional, tag = "3")]
+    pub timestamp: Option<u64>,
+}
+
+/// One update - trip update, vehicle position or alert.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FeedEntity {
+    #[prost(string, required, tag = "1")]
+    pub id: String,
+    #[prost(bool, optional, tag = "2")]
+    pub is_deleted: Option<bool>,
+    #[prost(message, optional, tag = "3")]
+    pub trip_update: Option<TripUpdate>,
+    #[prost(message, optional, tag = "4")]
+    pub vehicle: Option<VehiclePosition>,
+    #[prost(message, optional, tag = "5")]
+    pub alert: Option<Alert>,
+}
+
+/// Realtime progress of one trip.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TripUpdate {
+    #[prost(message, required, tag = "1")]
+    pub trip: TripDescriptor,
+    #[prost(message, repeated, tag = "2")]
+    pub stop_time_update: Vec<trip_update::StopTimeUpdate>,
+    #[prost(uint64, optional, tag = "4")]
+    pub timestamp: Option<u64>,
+    #[prost(int32, optional, tag = "5")]
+    pub delay: Option<i32>,
+}
+
+/// Messages nested in `TripUpdate`.
+pub mod trip_update {
+    /// Predicted arrival or departure - delay or absolute time.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StopTimeEvent {
+        #[prost(int32, optional, tag = "1")]
+        pub delay: Option<i32>,
+        #[prost(int64, optional, tag = "2")]
+        pub time: Option<i64>,
+    }
+
+    /// Prediction for one stop of the trip.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StopTimeUpdate {
+        #[prost(uint32, optional, tag = "1")]
+        pub stop_sequence: Option<u32>,
+        #[prost(string, optional, tag = "4")]
+        pub stop_id: Option<String>,
+        #[prost(message, optional, tag = "2")]
+        pub arrival: Option<StopTimeEvent>,
+        #[prost(message, optional, tag = "3")]
+        pub departure: Option<StopTimeEvent>,
+        #[prost(enumeration = "ScheduleRelationship", optional, tag = "5")]
+        pub schedule_relationship: Option<i32>,
+    }
+
+    /// Whether the trip stops at the stop as scheduled.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum ScheduleRelationship {
+        Scheduled = 0,
+        Skipped = 1,
+        NoData = 2,
+        Unscheduled = 3,
+    }
+}
+
+/// Where the vehicle serving a trip is.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VehiclePosition {
+    #[prost(message, optional, tag = "1")]
+    pub trip: Option<TripDescriptor>,
+    #[prost(uint32, optional, tag = "3")]
+    pub current_stop_sequence: Option<u32>,
+    #[prost(string, optional, tag = "7")]
+    pub stop_id: Option<String>,
+    #[prost(
+        enumeration = "vehicle_position::VehicleStopStatus",
+        optional,
+        tag = "4"
+    )]
+    pub current_status: Option<i32>,
+    #[prost(uint64, optional, tag = "5")]
+    pub timestamp: Option<u64>,
+}
+
+/// Enums nested in `VehiclePosition`.
+pub mod vehicle_position {
+    /// Vehicle position relative to its current stop.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum VehicleStopStatus {
+        IncomingAt = 0,
+        StoppedAt = 1,
+        InTransitTo = 2,
+    }
+}
+
+/// Service alert for the given entities.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Alert {
+    #[prost(message, repeated, tag = "1")]
+    pub active_period: Vec<TimeRange>,
+    #[prost(message, repeated, tag = "5")]
+    pub informed_entity: Vec<EntitySelector>,
+    #[prost(message, optional, tag = "8")]
+    pub url: Option<TranslatedString>,
+    #[prost(message, optional, tag = "10")]
+    pub header_text: Option<TranslatedString>,
+    #[prost(message, optional, tag = "11")]
+    pub description_text: Option<TranslatedString>,
+}
+
+/// Period (POSIX times) an alert is active, open ended if a bound is missing.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TimeRange {
+    #[prost(uint64, optional, tag = "1")]
+    pub start: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    pub end: Option<u64>,
+}
+
+/// Agency, route, trip or stop an alert applies to.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EntitySelector {
+    #[prost(string, optional, tag = "1")]
+    pub agency_id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub route_id: Option<String>,
+    #[prost(int32, optional, tag = "3")]
+    pub route_type: Option<i32>,
+    #[prost(message, optional, tag = "4")]
+    pub trip: Option<TripDescriptor>,
+    #[prost(string, optional, tag = "5")]
+    pub stop_id: Option<String>,
+}
+
+/// Text in several languages.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TranslatedString {
+    #[prost(message, repeated, tag = "1")]
+    pub translation: Vec<translated_string::Translation>,
+}
+
+/// Messages nested in `TranslatedString`.
+pub mod translated_string {
+    /// Text in one language.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Translation {
+        #[prost(string, required, tag = "1")]
+        pub text: String,
+        #[prost(string, optional, tag = "2")]
+        pub language: Option<String>,
+    }
+}
+
+/// Identifies a trip instance.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TripDescriptor {
+    #[prost(string, optional, tag = "1")]
+    pub trip_id: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub route_id: Option<String>,
+    #[prost(uint32, optional, tag = "6")]
+    pub direction_id: Option<u32>,
+    #[prost(string, optional, tag = "2")]
+    pub start_time: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub start_date: Option<String>,
+    #[prost(
+        enumeration = "trip_descriptor::ScheduleRelationship",
+        optional,
+        tag = "4"
+    )]
+    pub schedule_relationship: Option<i32>,
+}
+
+/// Enums nested in `TripDescriptor`.
+pub mod trip_descriptor {
+    /// Relation of the trip to the static schedule.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum ScheduleRelationship {
+        Scheduled = 0,
+        Added = 1,
+        Unscheduled = 2,
+        Canceled = 3,
+        Replacement = 5,
+        Duplicated = 6,
+        Deleted = 7,
+    }
+}
@@ -0,0 +1,75 @@
+//! Next departures from your public transport stops, computed from GTFS
+//! data files and optionally GTFS Realtime feeds.
+//!
+//! The usual flow is:
+//!
+//! 1. [`config::Config`] keeps feeds (GTFS data files) and watched stops.
+//!    Stops are added from a parsed [`feed::Feed`], which builds their
+//!    [`db::Database`] - all stop times of the stop with calendars. The
+//!    databases are kept apart from the config file by [`cache::Cache`].
+//! 2. [`timetables::Timetables`] answer [`timetables::Query`] with one
+//!    [`timetables::Departure`] per stop.
+//! 3. [`realtime::Realtime`] data (delays, cancellations, alerts, vehicle
+//!    positions) are applied by `Timetables` once loaded.
+//! 4. [`output`] serializes departures into versioned JSON, CSV or NDJSON.
+//!
+//! The library neither prints nor reads stdin, all outcomes are returned.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use chrono::{Duration, Utc};
+//! use transpors::config::Config;
+//! use transpors::timetables::{Board, Query, Timetables};
+//!
+//! # async fn run() -> transpors::Result<()> {
+//! let mut config = Config::init(None, "https://example.com/gtfs.zip").await?;
+//! let stop = config
+//!     .feed("default", &[])?
+//!     .find_stops("Central Station")
+//!     .first()
+//!     .cloned()
+//!     .ok_or_else(|| transpors::Error::UnknownStop("Central Station".to_owned()))?;
+//! let feed = config.feed("default", &[&stop.id])?;
+//! config.add_stop("default", &feed, &stop)?;
+//!
+//! let timetables = Timetables::from(Arc::new(config)).await?;
+//! let query = Query {
+//!     at: Utc::now(),
+//!     limit: 5,
+//!     look_ahead: Duration::hours(1),
+//!     board: Board::Departures,
+//!     show_all: false,
+//! };
+//!
+//! for departure in timetables.get_departures(&query) {
+//!     for next in departure.departures.iter() {
+//!         println!("{} {}", next.record.route, next.expected_time());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+/// Cache of stop databases derived from feeds.
+pub mod cache;
+/// Feeds and stops configuration.
+pub mod config;
+/// Per stop databases of stop times.
+pub mod db;
+/// Error type of all fallible operations.
+pub mod error;
+/// GTFS data file parsing.
+pub mod feed;
+/// GTFS Realtime protocol buffer messages.
+pub mod gtfs_realtime;
+/// Structured (JSON, CSV, NDJSON) output.
+pub mod output;
+/// GTFS Realtime predictions, alerts and vehicle positions.
+pub mod realtime;
+/// Fuzzy stop search.
+pub mod search;
+/// Departures lookup.
+pub mod timetables;
+
+pub use error::{Error, Result};
@@ -1,25 +1,446 @@
-mod config;
-mod db;
-mod timetables;
+mod cli;
+mod server;
+mod tui;
 mod ui;
-mod utils;
 
-use std::rc::Rc;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
 
-use config::Config;
-use timetables::Timetables;
-use ui::Ui;
+use chrono::{Duration, Local, TimeZone, Utc};
+use structopt::StructOpt;
+
+use transpors::config::{Config, FeedUpdate, DEFAULT_FEED};
+use transpors::error::{Error, Result};
+use transpors::feed::Validity;
+use transpors::output::{self, Format};
+use transpors::timetables::{Board, Query, Timetables};
+
+use cli::{Command, FeedCommand, Opt, ShowOpt, StopCommand, WatchOpt};
+use server::Server;
+use tui::Tui;
+use ui::{Ui, Wizard};
 
 #[tokio::main]
-pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn main() {
+    let opt = Opt::from_args();
+
+    if let Err(err) = run(opt).await {
+        eprintln!("Error: {}", err);
+        std::process::exit(err.exit_code());
+    }
+}
+
+async fn run(opt: Opt) -> Result<()> {
+    match opt.command {
+        Some(Command::Init { feed, name }) => init(&feed, name.as_deref()).await,
+        Some(Command::Feed(command)) => feed(command).await,
+        Some(Command::Stop(command)) => stop(command).await,
+        Some(Command::Update { feed }) => update(feed).await,
+        Some(Command::Show(show_opt)) => show(show_opt).await,
+        Some(Command::Watch(watch_opt)) => watch(watch_opt).await,
+        Some(Command::Serve {
+            bind,
+            realtime_interval,
+            no_realtime,
+        }) => serve(bind, realtime_interval, no_realtime).await,
+        None => show(opt.show).await,
+    }
+}
+
+async fn init(feed: &str, name: Option<&str>) -> Result<()> {
+    Config::init(name, feed).await?;
+    println!("Config created, add stops with \"transpors stop add <stop>\".");
+
+    Ok(())
+}
+
+/// Loads config. If there is none the wizard is run to create it.
+async fn load_or_create_config() -> Result<Config> {
+    if let Some(config) = Config::load().await? {
+        return Ok(config);
+    }
+
+    let data_file_path = Config::data_file_path(DEFAULT_FEED)?;
+    let mut wizard = Wizard::new(&data_file_path).await;
+    let output = wizard.run_wizard().await?;
+
+    Config::create(
+        &wizard.data_file_url.unwrap_or_default(),
+        wizard.data_file_version.unwrap_or_default(),
+        output.stops,
+    )
+    .await
+}
+
+async fn load_config() -> Result<Config> {
+    Config::load().await?.ok_or(Error::NoConfig)
+}
+
+async fn feed(command: FeedCommand) -> Result<()> {
+    let mut config = load_config().await?;
+
+    match command {
+        FeedCommand::Add { name, path_or_url } => {
+            let added = config.add_feed(&name, &path_or_url).await?;
+            println!("Added feed {}", added.name);
+        }
+        FeedCommand::Remove { name } => {
+            let removed = config.remove_feed(&name).await?;
+            println!("Removed feed {} and its stops", removed.name);
+        }
+        FeedCommand::Realtime {
+            name,
+            trip_updates,
+            alerts,
+            vehicle_positions,
+        } => {
+            let source = config.feed_source_mut(&name)?;
+
+            // Empty value unsets the source.
+            let set = |value: String| Some(value).filter(|v| !v.is_empty());
+
+            if let Some(trip_updates) = trip_updates {
+                source.trip_updates_url = set(trip_updates);
+            }
+
+            if let Some(alerts) = alerts {
+                source.alerts_url = set(alerts);
+            }
+
+            if let Some(vehicle_positions) = vehicle_positions {
+                source.vehicle_positions_url = set(vehicle_positions);
+            }
+
+            println!("Realtime sources of feed {} set", name);
+        }
+        FeedCommand::List => {
+            for feed in &config.feeds {
+                println!("{} {}", feed.name, feed.data_file_url);
+            }
+
+            return Ok(());
+        }
+    }
+
+    config.save().await?;
+
+    Ok(())
+}
+
+async fn stop(command: StopCommand) -> Result<()> {
+    let mut config = load_config().await?;
+
+    match command {
+        StopCommand::Add { stop, feed } => {
+            // Search thru the given feed or all of them.
+            let feed_names: Vec<String> = match feed {
+                Some(name) => vec![name],
+                None => config.feeds.iter().map(|f| f.name.clone()).collect(),
+            };
+
+            let mut candidates = vec![];
+
+            for feed_name in feed_names {
+                let found = config.feed(&feed_name, &[])?.find_stops(&stop);
+
+                if !found.is_empty() {
+                    candidates.push((feed_name, found));
+                }
+            }
+
+            match candidates.iter().map(|c| c.1.len()).sum() {
+                0 => return Err(Error::UnknownStop(stop)),
+                1 => {
+                    let (feed_name, found) = &candidates[0];
+                    let feed = config.feed(feed_name, &[&found[0].id])?;
+                    let added = config.add_stop(feed_name, &feed, &found[0])?;
+                    println!("Added stop {} ({})", added.display_name(), added.id);
+                }
+                _ => {
+                    println!(
+                        "More stops match {}, use stop ID (and --feed) instead:",
+                        stop
+                    );
+
+                    for (feed_name, found) in &candidates {
+                        for s in found {
+                            println!("{} {} {}", feed_name, s.id, s.name);
+                        }
+                    }
+
+                    return Err(Error::AmbiguousStop(stop));
+                }
+            }
+        }
+        StopCommand::Remove { stop } => {
+            let removed = config.remove_stop(&stop)?;
+            println!("Removed stop {} ({})", removed.display_name(), removed.id);
+        }
+        StopCommand::Search { query, feed, limit } => {
+            let feed_names: Vec<String> = match feed {
+                Some(name) => vec![name],
+                None => config.feeds.iter().map(|f| f.name.clone()).collect(),
+            };
+
+            let mut feeds = HashMap::new();
+            let mut found = vec![];
+
+            for feed_name in feed_names {
+                let feed = config.feed(&feed_name, &[])?;
+
+                for (stop, score) in feed.search_stops(&query) {
+                    found.push((score, feed_name.clone(), stop));
+                }
+
+                feeds.insert(feed_name, feed);
+            }
+
+            if found.is_empty() {
+                return Err(Error::UnknownStop(query));
+            }
+
+            // Most relevant first, feeds keep their order on ties.
+            found.sort_by_key(|(score, _, _)| Reverse(*score));
+
+            // Platforms are listed (indented) under their station.
+            let mut listed = HashSet::new();
+
+            for (_, feed_name, stop) in found.iter().take(limit) {
+                let feed = &feeds[feed_name];
+
+                for (station, platforms) in feed.group_by_station(std::slice::from_ref(stop)) {
+                    if !listed.insert((feed_name, station.id.clone())) {
+                        continue;
+                    }
+
+                    if platforms.is_empty() {
+                        println!("{} {}", feed_name, format_found_stop(&station));
+                        continue;
+                    }
+
+                    println!("{} {} (station)", feed_name, format_found_stop(&station));
+
+                    for platform in platforms {
+                        match feed.platform_code(&platform.id) {
+                            Some(code) => println!(
+                                "{}   {} (platform {})",
+                                feed_name,
+                                format_found_stop(&platform),
+                                code
+                            ),
+                            None => println!("{}   {}", feed_name, format_found_stop(&platform)),
+                        }
+                    }
+                }
+            }
+
+            return Ok(());
+        }
+        StopCommand::Filter {
+            stop,
+            routes,
+            directions,
+            clear,
+        } => {
+            let stop = config.stop_mut(&stop)?;
+
+            if !clear && routes.is_empty() && directions.is_empty() {
+                // Shown directions are marked.
+                for direction in stop.database.directions().iter() {
+                    let shown =
+                        stop.shows_direction(&direction.route, direction.headsign.as_deref());
+                    println!("{} {}", if shown { "*" } else { " " }, direction);
+                }
+
+                return Ok(());
+            }
+
+            stop.set_filters(routes, directions)?;
+
+            if clear {
+                println!("Showing all departures from {}", stop.display_name());
+            } else {
+                println!("Filters of stop {} set", stop.display_name());
+            }
+        }
+        StopCommand::List => {
+            for stop in &config.stops {
+                println!(
+                    "{} {} {} ({} records)",
+                    stop.feed,
+                    stop.id,
+                    stop.display_name(),
+                    stop.database.records.len()
+                );
+            }
+
+            return Ok(());
+        }
+    }
+
+    config.save().await?;
+
+    Ok(())
+}
+
+async fn update(feed: Option<String>) -> Result<()> {
+    let mut config = load_config().await?;
+
+    let feed_names: Vec<String> = match feed {
+        Some(name) => vec![name],
+        None => config.feeds.iter().map(|f| f.name.clone()).collect(),
+    };
+
+    for feed_name in feed_names {
+        println!("Updating feed {}", feed_name);
+
+        match config.update_feed(&feed_name).await? {
+            FeedUpdate::Unchanged => println!("No changes."),
+            FeedUpdate::Updated {
+                added_routes,
+                removed_routes,
+                old_validity,
+                new_validity,
+                missing_stops,
+            } => {
+                if !added_routes.is_empty() {
+                    println!("New routes: {}", added_routes.join(", "));
+                }
+
+                if !removed_routes.is_empty() {
+                    println!("Removed routes: {}", removed_routes.join(", "));
+                }
+
+                if old_validity != new_validity {
+                    let format = |v: Option<Validity>| match v {
+                        Some(v) => v.to_string(),
+                        None => "unknown".to_owned(),
+                    };
+
+                    println!(
+                        "Validity changed from {} to {}",
+                        format(old_validity),
+                        format(new_validity)
+                    );
+                }
+
+                for stop in missing_stops {
+                    println!(
+                        "Stop {} is no longer in the feed, keeping old timetable.",
+                        stop
+                    );
+                }
+
+                println!("Feed updated.");
+            }
+        }
+
+        // Save after each feed so finished updates are kept.
+        config.save().await?;
+    }
+
+    Ok(())
+}
+
+async fn show(opt: ShowOpt) -> Result<()> {
     // Create/get config.
-    let config = Rc::new(Config::new().await?);
+    let config = Arc::new(load_or_create_config().await?);
+    let query = query(&opt)?;
 
-    let timetables = Timetables::from(config.clone()).await?;
-    let departures = timetables.get_departures();
+    let mut timetables = Timetables::from(config.clone()).await?;
 
-    let ui = Ui::new(&config);
-    ui.output(departures);
+    if !opt.no_realtime {
+        for (feed, err) in timetables.load_realtime().await {
+            // Stderr keeps structured output parseable.
+            eprintln!(
+                "Warning: realtime data of feed {} unavailable: {}",
+                feed, err
+            );
+        }
+    }
+
+    let departures = timetables.get_departures(&query);
+
+    if opt.format != Format::Text {
+        return output::write(io::stdout().lock(), opt.format, &departures, query.at);
+    }
+
+    let ui = Ui::new();
+
+    if opt.merge {
+        ui.output_merged(departures, query.limit);
+    } else {
+        ui.output(departures);
+    }
 
     Ok(())
 }
+
+async fn watch(opt: WatchOpt) -> Result<()> {
+    let config = Arc::new(load_or_create_config().await?);
+    let query = query(&opt.show)?;
+    let timetables = Timetables::from(config).await?;
+
+    let realtime_interval = if opt.show.no_realtime {
+        None
+    } else {
+        Some(StdDuration::from_secs(opt.realtime_interval.max(1)))
+    };
+
+    Tui::new(
+        timetables,
+        query,
+        StdDuration::from_secs(opt.interval.max(1)),
+        realtime_interval,
+    )
+    .run()
+    .await
+}
+
+async fn serve(bind: SocketAddr, realtime_interval: u64, no_realtime: bool) -> Result<()> {
+    let config = Arc::new(load_config().await?);
+    let realtime_interval = if no_realtime {
+        None
+    } else {
+        Some(StdDuration::from_secs(realtime_interval.max(1)))
+    };
+
+    Server::new(config)
+        .await?
+        .run(bind, realtime_interval)
+        .await
+}
+
+/// Builds departures query from command line options.
+fn query(opt: &ShowOpt) -> Result<Query> {
+    Ok(Query {
+        at: match opt.at {
+            Some(at) => Local
+                .from_local_datetime(&at)
+                .earliest()
+                .ok_or_else(|| Error::InvalidInput(format!("Invalid reference time {}", at)))?
+                .with_timezone(&Utc),
+            None => Utc::now(),
+        },
+        limit: opt.limit,
+        look_ahead: Duration::minutes(opt.window),
+        board: if opt.arrivals {
+            Board::Arrivals
+        } else {
+            Board::Departures
+        },
+        show_all: opt.all,
+    })
+}
+
+/// Stop search result line - ID, name and code (if any).
+fn format_found_stop(stop: &gtfs_structures::Stop) -> String {
+    match &stop.code {
+        Some(code) => format!("{} {} [{}]", stop.id, stop.name, code),
+        None => format!("{} {}", stop.id, stop.name),
+    }
+}
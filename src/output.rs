@@ -0,0 +1,317 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::config::Stop;
+use crate::error::{Error, Result};
+use crate::realtime::{Proximity, ServiceAlert};
+use crate::timetables::{Departure, FeedStatus, NextDeparture};
+
+/// Version of the structured output schema. Bump it whenever a field is
+/// removed, renamed or changes its meaning (adding fields is fine).
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How departures are printed.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Human readable board, not covered by `SCHEMA_VERSION`.
+    Text,
+    /// One JSON document with all stops.
+    Json,
+    /// One row per departure, with a header row.
+    Csv,
+    /// One JSON object per stop and line.
+    Ndjson,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err(format!(
+                "Unknown format {}, use text, json, csv or ndjson",
+                s
+            )),
+        }
+    }
+}
+
+/// Configured stop in the stop list.
+#[derive(Serialize)]
+struct StopRow<'a> {
+    id: &'a str,
+    name: &'a str,
+    feed: &'a str,
+    /// Platform code if the stop is a single platform of a station.
+    platform: Option<&'a str>,
+    /// IANA name of the timezone departure times are shown in.
+    timezone: &'a str,
+}
+
+#[derive(Serialize)]
+struct StopList<'a> {
+    schema_version: u32,
+    stops: Vec<StopRow<'a>>,
+}
+
+/// Whole JSON document.
+#[derive(Serialize)]
+struct Document<'a> {
+    schema_version: u32,
+    /// Reference time departures are looked up for.
+    at: DateTime<Utc>,
+    stops: Vec<StopBoard<'a>>,
+}
+
+/// One NDJSON line.
+#[derive(Serialize)]
+struct Line<'a> {
+    schema_version: u32,
+    at: DateTime<Utc>,
+    #[serde(flatten)]
+    stop: StopBoard<'a>,
+}
+
+#[derive(Serialize)]
+struct StopBoard<'a> {
+    stop_id: &'a str,
+    stop_name: &'a str,
+    feed: &'a str,
+    platform: Option<&'a str>,
+    /// "valid", "expired" or "not_yet_valid".
+    status: &'static str,
+    /// End (expired) or start (not yet valid) of the service period.
+    status_date: Option<NaiveDate>,
+    alerts: Vec<AlertRow<'a>>,
+    departures: Vec<DepartureRow<'a>>,
+}
+
+#[derive(Serialize)]
+struct AlertRow<'a> {
+    header: &'a str,
+    description: Option<&'a str>,
+    url: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct DepartureRow<'a> {
+    route: &'a str,
+    route_id: &'a str,
+    route_long_name: &'a str,
+    headsign: Option<&'a str>,
+    trip_id: &'a str,
+    /// RFC 3339 time in the agency timezone.
+    scheduled: String,
+    realtime: Option<String>,
+    delay_minutes: Option<i64>,
+    /// Minutes from the reference time to the expected departure.
+    minutes_until: i64,
+    cancelled: bool,
+    /// Set for trips that run every N minutes instead of on schedule.
+    headway_minutes: Option<u32>,
+    /// "at_stop", "arriving" or "approaching".
+    vehicle_status: Option<&'static str>,
+    stops_away: Option<u32>,
+    terminates: bool,
+    /// Platform the departure leaves from (if known).
+    platform: Option<&'a str>,
+}
+
+/// Flat CSV row, one per departure. Alerts aren't part of it. New
+/// columns are appended, so positions of the others stay the same.
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    schema_version: u32,
+    stop_id: &'a str,
+    stop_name: &'a str,
+    feed: &'a str,
+    route: &'a str,
+    headsign: Option<&'a str>,
+    trip_id: &'a str,
+    scheduled: &'a str,
+    realtime: Option<&'a str>,
+    delay_minutes: Option<i64>,
+    minutes_until: i64,
+    cancelled: bool,
+    headway_minutes: Option<u32>,
+    vehicle_status: Option<&'a str>,
+    stops_away: Option<u32>,
+    terminates: bool,
+    platform: Option<&'a str>,
+    route_id: &'a str,
+}
+
+/// Writes departures in the given (structured) format.
+pub fn write<W: Write>(
+    mut out: W,
+    format: Format,
+    departures: &[Departure],
+    at: DateTime<Utc>,
+) -> Result<()> {
+    let stops = departures.iter().map(|d| stop_board(d, at));
+
+    match format {
+        Format::Text => {
+            return Err(Error::InvalidInput(
+                "Text format isn't structured".to_owned(),
+            ))
+        }
+        Format::Json => {
+            let document = Document {
+                schema_version: SCHEMA_VERSION,
+                at,
+                stops: stops.collect(),
+            };
+
+            serde_json::to_writer_pretty(&mut out, &document).map_err(output_error)?;
+            writeln!(out)?;
+        }
+        Format::Ndjson => {
+            for stop in stops {
+                let line = Line {
+                    schema_version: SCHEMA_VERSION,
+                    at,
+                    stop,
+                };
+
+                serde_json::to_writer(&mut out, &line).map_err(output_error)?;
+                writeln!(out)?;
+            }
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+
+            for stop in stops {
+                for departure in stop.departures.iter() {
+                    writer
+                        .serialize(CsvRow {
+                            schema_version: SCHEMA_VERSION,
+                            stop_id: stop.stop_id,
+                            stop_name: stop.stop_name,
+                            feed: stop.feed,
+                            route: departure.route,
+                            headsign: departure.headsign,
+                            trip_id: departure.trip_id,
+                            scheduled: &departure.scheduled,
+                            realtime: departure.realtime.as_deref(),
+                            delay_minutes: departure.delay_minutes,
+                            minutes_until: departure.minutes_until,
+                            cancelled: departure.cancelled,
+                            headway_minutes: departure.headway_minutes,
+                            vehicle_status: departure.vehicle_status,
+                            stops_away: departure.stops_away,
+                            terminates: departure.terminates,
+                            platform: departure.platform,
+                            route_id: departure.route_id,
+                        })
+                        .map_err(output_error)?;
+                }
+            }
+
+            writer.flush().map_err(output_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// JSON of one stop departures - the same object as one NDJSON line.
+pub fn stop_json(departure: &Departure, at: DateTime<Utc>) -> serde_json::Result<String> {
+    serde_json::to_string(&Line {
+        schema_version: SCHEMA_VERSION,
+        at,
+        stop: stop_board(departure, at),
+    })
+}
+
+/// JSON list of configured stops.
+pub fn stops_json(stops: &[Stop]) -> serde_json::Result<String> {
+    serde_json::to_string(&StopList {
+        schema_version: SCHEMA_VERSION,
+        stops: stops
+            .iter()
+            .map(|s| StopRow {
+                id: &s.id,
+                name: &s.name,
+                feed: &s.feed,
+                platform: s.platform.as_deref(),
+                timezone: &s.database.timezone,
+            })
+            .collect(),
+    })
+}
+
+/// Turns any serialization (or writing) failure into output error.
+fn output_error<E: std::error::Error>(err: E) -> Error {
+    Error::Output(err.to_string())
+}
+
+fn stop_board<'a>(departure: &'a Departure, at: DateTime<Utc>) -> StopBoard<'a> {
+    let (status, status_date) = match departure.status {
+        FeedStatus::Valid => ("valid", None),
+        FeedStatus::Expired(end) => ("expired", Some(end)),
+        FeedStatus::NotYetValid(start) => ("not_yet_valid", Some(start)),
+    };
+
+    StopBoard {
+        stop_id: &departure.stop.id,
+        stop_name: &departure.stop.name,
+        feed: &departure.stop.feed,
+        platform: departure.stop.platform.as_deref(),
+        status,
+        status_date,
+        alerts: departure.alerts.iter().map(alert_row).collect(),
+        departures: departure
+            .departures
+            .iter()
+            .map(|d| departure_row(d, at))
+            .collect(),
+    }
+}
+
+fn alert_row(alert: &ServiceAlert) -> AlertRow<'_> {
+    AlertRow {
+        header: &alert.header,
+        description: alert.description.as_deref(),
+        url: alert.url.as_deref(),
+    }
+}
+
+fn departure_row<'a>(next_departure: &NextDeparture<'a>, at: DateTime<Utc>) -> DepartureRow<'a> {
+    let record = next_departure.record;
+    let (vehicle_status, stops_away) = match next_departure.proximity {
+        Some(Proximity::AtStop) => (Some("at_stop"), Some(0)),
+        Some(Proximity::Arriving) => (Some("arriving"), Some(0)),
+        Some(Proximity::StopsAway(n)) => (Some("approaching"), Some(n)),
+        None => (None, None),
+    };
+
+    DepartureRow {
+        route: &record.route,
+        route_id: &record.route_id,
+        route_long_name: &record.route_long_name,
+        headsign: record.headsign.as_deref(),
+        trip_id: &record.trip,
+        scheduled: rfc3339(next_departure.time),
+        realtime: next_departure.realtime.map(rfc3339),
+        delay_minutes: next_departure.delay().map(|d| d.num_minutes()),
+        minutes_until: (next_departure.expected_time().with_timezone(&Utc) - at).num_minutes(),
+        cancelled: next_departure.cancelled,
+        headway_minutes: record.headway.map(|h| h.div_ceil(60)),
+        vehicle_status,
+        stops_away,
+        terminates: record.last_stop,
+        platform: record.platform.as_deref(),
+    }
+}
+
+fn rfc3339(time: DateTime<Tz>) -> String {
+    time.to_rfc3339()
+}
@@ -0,0 +1,351 @@
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use prost::Message;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::env;
+use tokio::fs;
+
+use crate::config::Stop;
+use crate::db::{Database, Record};
+use crate::error::{describe, Error, Result};
+use crate::gtfs_realtime::trip_descriptor::ScheduleRelationship as TripRelationship;
+use crate::gtfs_realtime::trip_update::{ScheduleRelationship, StopTimeEvent, StopTimeUpdate};
+use crate::gtfs_realtime::vehicle_position::VehicleStopStatus;
+use crate::gtfs_realtime::{
+    Alert, EntitySelector, FeedMessage, TranslatedString, TripDescriptor, TripUpdate,
+    VehiclePosition,
+};
+use crate::timetables::Board;
+
+/// Realtime state of one departure.
+pub enum Prediction {
+    /// Expected stop time.
+    At(DateTime<Tz>),
+    /// Trip doesn't stop at the stop (or doesn't run at all).
+    Cancelled,
+}
+
+/// Position of the vehicle serving a trip relative to the stop.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Proximity {
+    /// Vehicle stands at the stop.
+    AtStop,
+    /// Vehicle is on its way from the previous stop.
+    Arriving,
+    /// Number of stops the vehicle is yet to reach (or stand at) before
+    /// this one.
+    StopsAway(u32),
+}
+
+/// Service alert texts in the user's language (if provided by the
+/// feed).
+#[derive(Clone, Debug)]
+pub struct ServiceAlert {
+    /// Short summary.
+    pub header: String,
+    /// Full text.
+    pub description: Option<String>,
+    /// Page with more details.
+    pub url: Option<String>,
+}
+
+/// IDs alert selectors are matched against - of the stop (and its
+/// platforms) and of agencies, routes and trips calling there.
+struct Informed<'a> {
+    agency_ids: HashSet<&'a str>,
+    route_ids: HashSet<&'a str>,
+    trip_ids: HashSet<&'a str>,
+    stop_ids: HashSet<&'a str>,
+}
+
+/// GTFS Realtime data of one feed.
+#[derive(Default)]
+pub struct Realtime {
+    /// Trip ID -> trip updates (more of them if the trip runs on more
+    /// service days).
+    trip_updates: HashMap<String, Vec<TripUpdate>>,
+    /// Trip ID -> vehicle positions.
+    vehicles: HashMap<String, Vec<VehiclePosition>>,
+    alerts: Vec<Alert>,
+}
+
+impl Realtime {
+    /// Fetches GTFS Realtime feed from the given URL (or local file) and
+    /// adds its entities to the already loaded ones.
+    pub async fn load(&mut self, path_or_url: &str) -> Result<()> {
+        let realtime_error = |err: &dyn std::error::Error| Error::Realtime {
+            url: path_or_url.to_owned(),
+            message: describe(err),
+        };
+
+        let data = if path_or_url.starts_with("http") {
+            let response = reqwest::get(path_or_url)
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|err| realtime_error(&err))?;
+
+            response
+                .bytes()
+                .await
+                .map_err(|err| realtime_error(&err))?
+                .to_vec()
+        } else {
+            fs::read(path_or_url)
+                .await
+                .map_err(|err| realtime_error(&err))?
+        };
+
+        self.add(FeedMessage::decode(&data[..]).map_err(|err| realtime_error(&err))?);
+
+        Ok(())
+    }
+
+    fn add(&mut self, message: FeedMessage) {
+        for entity in message.entity {
+            if entity.is_deleted == Some(true) {
+                continue;
+            }
+
+            if let Some(trip_update) = entity.trip_update {
+                if let Some(trip_id) = trip_update.trip.trip_id.clone() {
+                    self.trip_updates
+                        .entry(trip_id)
+                        .or_default()
+                        .push(trip_update);
+                }
+            }
+
+            if let Some(vehicle) = entity.vehicle {
+                if let Some(trip_id) = vehicle.trip.as_ref().and_then(|t| t.trip_id.clone()) {
+                    self.vehicles.entry(trip_id).or_default().push(vehicle);
+                }
+            }
+
+            if let Some(alert) = entity.alert {
+                self.alerts.push(alert);
+            }
+        }
+    }
+
+    /// Returns alerts active at the given moment that affect the stop or
+    /// any agency, route or trip serving it.
+    pub fn alerts(&self, stop: &Stop, at: DateTime<Utc>) -> Vec<ServiceAlert> {
+        let records = &stop.database.records;
+        let informed = Informed {
+            agency_ids: records.iter().map(|r| &*r.agency_id).collect(),
+            route_ids: records.iter().map(|r| &*r.route_id).collect(),
+            trip_ids: records.iter().map(|r| r.trip.as_str()).collect(),
+            stop_ids: records
+                .iter()
+                .map(|r| r.stop_id.as_str())
+                .chain(std::iter::once(stop.id.as_str()))
+                .collect(),
+        };
+
+        self.alerts
+            .iter()
+            .filter(|a| Self::is_active(a, at))
+            .filter(|a| {
+                a.informed_entity
+                    .iter()
+                    .any(|e| Self::informs(e, &informed))
+            })
+            .filter_map(|a| {
+                Some(ServiceAlert {
+                    header: Self::translate(a.header_text.as_ref()?)?,
+                    description: a.description_text.as_ref().and_then(Self::translate),
+                    url: a.url.as_ref().and_then(Self::translate),
+                })
+            })
+            .collect()
+    }
+
+    /// Alert without active periods is active all the time.
+    fn is_active(alert: &Alert, at: DateTime<Utc>) -> bool {
+        let at = at.timestamp() as u64;
+
+        alert.active_period.is_empty()
+            || alert.active_period.iter().any(|p| {
+                p.start.is_none_or(|start| start <= at) && p.end.is_none_or(|end| at <= end)
+            })
+    }
+
+    /// Tells whether the selector matches the stop, its agencies, routes
+    /// or trips. All the selector fields given must match.
+    fn informs(entity: &EntitySelector, informed: &Informed) -> bool {
+        let trip = entity.trip.as_ref();
+        let route_id = entity.route_id.as_ref().or_else(|| trip?.route_id.as_ref());
+        let selectors = [
+            (entity.agency_id.as_ref(), &informed.agency_ids),
+            (route_id, &informed.route_ids),
+            (trip.and_then(|t| t.trip_id.as_ref()), &informed.trip_ids),
+            (entity.stop_id.as_ref(), &informed.stop_ids),
+        ];
+
+        selectors.iter().any(|(id, _)| id.is_some())
+            && selectors
+                .iter()
+                .all(|(id, ids)| id.is_none_or(|id| ids.contains(id.as_str())))
+    }
+
+    /// Picks translation matching the language from `LANG` environment
+    /// variable, falls back to the first one.
+    fn translate(text: &TranslatedString) -> Option<String> {
+        let lang = env::var("LANG").unwrap_or_default();
+        let lang = lang.split(['_', '.']).next().unwrap_or("");
+
+        text.translation
+            .iter()
+            .find(|t| t.language.as_deref() == Some(lang))
+            .or_else(|| text.translation.first())
+            .map(|t| t.text.clone())
+    }
+
+    /// Predicts stop time of the given record. Returns `None` if there is
+    /// no realtime information for it.
+    pub fn predict(
+        &self,
+        record: &Record,
+        service_date: NaiveDate,
+        scheduled: DateTime<Tz>,
+        board: Board,
+    ) -> Option<Prediction> {
+        let trip_update = self
+            .trip_updates
+            .get(&record.trip)?
+            .iter()
+            .find(|u| Self::describes(&u.trip, record, service_date))?;
+
+        match trip_update
+            .trip
+            .schedule_relationship
+            .and_then(TripRelationship::from_i32)
+        {
+            Some(TripRelationship::Canceled) | Some(TripRelationship::Deleted) => {
+                return Some(Prediction::Cancelled)
+            }
+            _ => {}
+        }
+
+        // Update for this very stop.
+        if let Some(update) = trip_update.stop_time_update.iter().find(|u| {
+            u.stop_sequence == Some(record.stop_sequence.into())
+                || (u.stop_sequence.is_none() && u.stop_id.as_ref() == Some(&record.stop_id))
+        }) {
+            match update
+                .schedule_relationship
+                .and_then(ScheduleRelationship::from_i32)
+            {
+                Some(ScheduleRelationship::Skipped) => return Some(Prediction::Cancelled),
+                Some(ScheduleRelationship::NoData) => return None,
+                _ => {}
+            }
+
+            if let Some(event) = Self::event(update, board) {
+                // Times out of range are ignored, delay may still be given.
+                if let Some(time) = event.time.and_then(|t| Utc.timestamp_opt(t, 0).single()) {
+                    return Some(Prediction::At(time.with_timezone(&scheduled.timezone())));
+                }
+
+                if let Some(delay) = event.delay {
+                    return Some(Prediction::At(scheduled + Duration::seconds(delay.into())));
+                }
+            }
+        }
+
+        // Delay propagates from the closest preceding stop.
+        let preceding_delay = trip_update
+            .stop_time_update
+            .iter()
+            .filter(|u| {
+                u.stop_sequence
+                    .is_some_and(|s| s < u32::from(record.stop_sequence))
+            })
+            .max_by_key(|u| u.stop_sequence)
+            .and_then(|u| {
+                if u.schedule_relationship == Some(ScheduleRelationship::NoData as i32) {
+                    return None;
+                }
+
+                u.departure.as_ref().or(u.arrival.as_ref())?.delay
+            });
+
+        preceding_delay
+            .or(trip_update.delay)
+            .map(|delay| Prediction::At(scheduled + Duration::seconds(delay.into())))
+    }
+
+    /// Tells how far the vehicle serving the record's trip is from the
+    /// stop (the database of the record is needed to tell positions of
+    /// stop sequences). Returns `None` if the vehicle is unknown or has
+    /// already left.
+    pub fn proximity(
+        &self,
+        record: &Record,
+        database: &Database,
+        service_date: NaiveDate,
+    ) -> Option<Proximity> {
+        let vehicle = self.vehicles.get(&record.trip)?.iter().find(|v| {
+            v.trip
+                .as_ref()
+                .is_some_and(|t| Self::describes(t, record, service_date))
+        })?;
+        let status = vehicle
+            .current_status
+            .and_then(VehicleStopStatus::from_i32)
+            .unwrap_or(VehicleStopStatus::InTransitTo);
+
+        let stops_away = match vehicle.current_stop_sequence {
+            // Stop sequences may have gaps, positions within the trip
+            // are compared.
+            Some(sequence) => {
+                let position =
+                    database.stop_position(&record.trip, u16::try_from(sequence).ok()?)?;
+
+                u32::from(record.position.checked_sub(position)?)
+            }
+            // Without sequence only the vehicle at (heading to) this very
+            // stop can be told.
+            None if vehicle.stop_id.as_ref() == Some(&record.stop_id) => 0,
+            None => return None,
+        };
+
+        Some(match (stops_away, status) {
+            (0, VehicleStopStatus::StoppedAt) => Proximity::AtStop,
+            (0, _) => Proximity::Arriving,
+            (n, _) => Proximity::StopsAway(n),
+        })
+    }
+
+    /// Tells whether the trip descriptor is about the record trip on the
+    /// given service day. Trips expanded from frequencies share the trip
+    /// ID, so their start time has to match too.
+    fn describes(trip: &TripDescriptor, record: &Record, service_date: NaiveDate) -> bool {
+        let starts_at = match record.trip_start {
+            Some(start) => {
+                trip.start_time
+                    .as_deref()
+                    .and_then(|t| gtfs_structures::parse_time(t).ok())
+                    == Some(start)
+            }
+            None => true,
+        };
+
+        starts_at && Self::runs_on(trip, service_date)
+    }
+
+    /// Trip descriptor without start date matches any service day.
+    fn runs_on(trip: &TripDescriptor, service_date: NaiveDate) -> bool {
+        trip.start_date.is_none()
+            || trip.start_date.as_deref() == Some(&service_date.format("%Y%m%d").to_string())
+    }
+
+    /// Returns stop time event relevant for the given board.
+    fn event(update: &StopTimeUpdate, board: Board) -> Option<&StopTimeEvent> {
+        match board {
+            Board::Departures => update.departure.as_ref().or(update.arrival.as_ref()),
+            Board::Arrivals => update.arrival.as_ref().or(update.departure.as_ref()),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use gtfs_structures::Stop;
+
+/// Lowest word similarity (0 - 1) still taken as a typo of the word.
+const MIN_SIMILARITY: f64 = 0.8;
+/// Shorter query words have to match exactly, as almost any word of
+/// similar length resembles them ("nam" and "nad").
+const MIN_FUZZY_LENGTH: usize = 4;
+
+/// How well the stop matches the query, higher is better. Scores of the
+/// match kinds don't overlap, so e.g. any exact name match ranks above
+/// any prefix match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Score(u32);
+
+impl Score {
+    const ID: Score = Score(1000);
+    const CODE: Score = Score(950);
+    const NAME: Score = Score(900);
+    const NAME_PREFIX: Score = Score(800);
+    const WORD_PREFIX: Score = Score(700);
+    const SUBSTRING: Score = Score(600);
+    const WORDS: Score = Score(500);
+    /// Fuzzy matches score up to this by word similarity.
+    const FUZZY: u32 = 400;
+
+    /// Exact stop ID or stop code match.
+    pub fn is_direct(self) -> bool {
+        self >= Score::CODE
+    }
+
+    /// Exact (case and diacritics insensitive) name match, or better.
+    pub fn is_exact(self) -> bool {
+        self >= Score::NAME
+    }
+}
+
+/// Stop search query, normalized once for matching many stops.
+pub struct Query<'a> {
+    raw: &'a str,
+    normalized: String,
+    words: Vec<String>,
+}
+
+impl<'a> Query<'a> {
+    /// Query of the given text, which is a stop ID, code or (part of) name.
+    pub fn new(query: &'a str) -> Self {
+        let normalized = normalize(query);
+        let words = normalized.split(' ').map(str::to_owned).collect();
+
+        Self {
+            raw: query.trim(),
+            normalized,
+            words,
+        }
+    }
+
+    /// Scores the stop, `None` if it doesn't match at all. Stop ID and
+    /// code have to match exactly (ignoring case), the name is matched
+    /// ignoring case and diacritics and tolerating typos.
+    pub fn score(&self, stop: &Stop) -> Option<Score> {
+        if self.normalized.is_empty() {
+            return None;
+        }
+
+        if stop.id.eq_ignore_ascii_case(self.raw) {
+            return Some(Score::ID);
+        }
+
+        if stop
+            .code
+            .as_ref()
+            .is_some_and(|code| code.eq_ignore_ascii_case(self.raw))
+        {
+            return Some(Score::CODE);
+        }
+
+        let name = normalize(&stop.name);
+        let name_words: Vec<&str> = name.split(' ').collect();
+
+        if name == self.normalized {
+            return Some(Score::NAME);
+        }
+
+        if name.starts_with(&self.normalized) {
+            return Some(Score::NAME_PREFIX);
+        }
+
+        if name_words.iter().any(|w| w.starts_with(&self.normalized)) {
+            return Some(Score::WORD_PREFIX);
+        }
+
+        if name.contains(&self.normalized) {
+            return Some(Score::SUBSTRING);
+        }
+
+        // Each query word begins some name word ("nam mir").
+        if self
+            .words
+            .iter()
+            .all(|q| name_words.iter().any(|w| w.starts_with(q.as_str())))
+        {
+            return Some(Score::WORDS);
+        }
+
+        // Each query word resembles some name word (or its beginning, so
+        // unfinished words match too). Short words have to begin some
+        // name word.
+        let mut similarity = 0.0;
+
+        for word in self.words.iter() {
+            if word.chars().count() < MIN_FUZZY_LENGTH {
+                if !name_words.iter().any(|w| w.starts_with(word.as_str())) {
+                    return None;
+                }
+
+                similarity += 1.0;
+                continue;
+            }
+
+            let best = name_words
+                .iter()
+                .map(|w| {
+                    let prefix: String = w.chars().take(word.chars().count()).collect();
+
+                    strsim::jaro_winkler(word, w).max(strsim::jaro_winkler(word, &prefix))
+                })
+                .fold(0.0, f64::max);
+
+            if best < MIN_SIMILARITY {
+                return None;
+            }
+
+            similarity += best;
+        }
+
+        let similarity = similarity / self.words.len() as f64;
+
+        Some(Score((similarity * Score::FUZZY as f64) as u32))
+    }
+}
+
+/// Lowercase ASCII transliteration with words separated by single spaces
+/// ("Náměstí  Míru!" -> "namesti miru").
+pub fn normalize(text: &str) -> String {
+    deunicode::deunicode(text)
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,374 @@
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+use tokio::sync::RwLock;
+
+use crate::cli::parse_datetime;
+use transpors::config::Config;
+use transpors::error::{Error, Result};
+use transpors::output::{self, SCHEMA_VERSION};
+use transpors::timetables::{Board, Query, Timetables, MAX_WINDOW_MINUTES};
+
+/// Default number of departures returned per stop.
+const DEFAULT_LIMIT: usize = 10;
+/// Maximum number of departures returned per stop.
+const MAX_LIMIT: usize = 100;
+/// Default look-ahead window (in minutes).
+const DEFAULT_WINDOW: i64 = 120;
+
+/// HTTP server answering departure queries from timetables loaded once
+/// at startup.
+pub struct Server {
+    config: Arc<Config>,
+    timetables: Arc<RwLock<Timetables>>,
+}
+
+impl Server {
+    pub async fn new(config: Arc<Config>) -> Result<Self> {
+        let timetables = Timetables::from(config.clone()).await?;
+
+        Ok(Self {
+            config,
+            timetables: Arc::new(RwLock::new(timetables)),
+        })
+    }
+
+    /// Serves requests until the process is killed. Realtime data are
+    /// fetched again every `realtime_interval` in background (never if
+    /// `None`).
+    pub async fn run(
+        &self,
+        bind: SocketAddr,
+        realtime_interval: Option<StdDuration>,
+    ) -> Result<()> {
+        if let Some(realtime_interval) = realtime_interval {
+            tokio::spawn(Self::refresh_realtime(
+                self.config.clone(),
+                self.timetables.clone(),
+                realtime_interval,
+            ));
+        }
+
+        let timetables = self.timetables.clone();
+        let make_service = make_service_fn(move |_| {
+            let timetables = timetables.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    Self::handle(timetables.clone(), request)
+                }))
+            }
+        });
+
+        println!("Listening on http://{}", bind);
+        hyper::Server::try_bind(&bind)
+            .map_err(|err| Error::Server(format!("can't listen on {}: {}", bind, err)))?
+            .serve(make_service)
+            .await
+            .map_err(|err| Error::Server(err.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn refresh_realtime(
+        config: Arc<Config>,
+        timetables: Arc<RwLock<Timetables>>,
+        interval: StdDuration,
+    ) {
+        loop {
+            // Fetch without lock so requests aren't blocked meanwhile.
+            let (realtime, errors) = Timetables::fetch_realtime(&config).await;
+
+            for (feed, err) in errors {
+                eprintln!(
+                    "Warning: realtime data of feed {} unavailable: {}",
+                    feed, err
+                );
+            }
+
+            timetables.write().await.set_realtime(realtime);
+            tokio::time::delay_for(interval).await;
+        }
+    }
+
+    async fn handle(
+        timetables: Arc<RwLock<Timetables>>,
+        request: Request<Body>,
+    ) -> std::result::Result<Response<Body>, Infallible> {
+        if request.method() != Method::GET {
+            return Ok(Self::error(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Only GET is supported",
+            ));
+        }
+
+        let timetables = timetables.read().await;
+        let path: Vec<&str> = request
+            .uri()
+            .path()
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        let response = match path.as_slice() {
+            ["health"] => Ok(format!(
+                "{{\"status\":\"ok\",\"schema_version\":{},\"stops\":{}}}",
+                SCHEMA_VERSION,
+                timetables.stops().len()
+            )),
+            ["stops"] => output::stops_json(timetables.stops()).map_err(Self::internal),
+            ["stops", stop_id, "departures"] => {
+                let stop_id = percent_decode(stop_id);
+                let params = Self::params(request.uri().query());
+                let feed = params.get("feed").map(String::as_str);
+
+                Self::query(&params).and_then(|query| {
+                    let departure = timetables
+                        .get_departures_by_id(&stop_id, feed, &query)
+                        .map_err(|err| match err {
+                            Error::AmbiguousStop(_) => (
+                                StatusCode::BAD_REQUEST,
+                                format!("Stop {} is in more feeds, give its feed", stop_id),
+                            ),
+                            _ => (
+                                StatusCode::NOT_FOUND,
+                                format!("No stop {} configured", stop_id),
+                            ),
+                        })?;
+
+                    output::stop_json(&departure, query.at).map_err(Self::internal)
+                })
+            }
+            _ => Err((StatusCode::NOT_FOUND, "Not found".to_string())),
+        };
+
+        Ok(match response {
+            Ok(body) => Self::json(StatusCode::OK, body),
+            Err((status, message)) => Self::error(status, &message),
+        })
+    }
+
+    /// Parses URL query string parameters.
+    fn params(query_string: Option<&str>) -> HashMap<String, String> {
+        url::form_urlencoded::parse(query_string.unwrap_or("").as_bytes())
+            .into_owned()
+            .collect()
+    }
+
+    /// Builds departures query from URL query parameters (`limit`, `at`,
+    /// `window`, `arrivals` and `all`). Limit and window out of range
+    /// are rejected, so one request can't hold timetables for long.
+    fn query(params: &HashMap<String, String>) -> std::result::Result<Query, (StatusCode, String)> {
+        let bad_request = |name: &str| (StatusCode::BAD_REQUEST, format!("Invalid {}", name));
+        let flag = |name: &str| params.get(name).is_some_and(|v| v != "false" && v != "0");
+
+        let at = match params.get("at") {
+            Some(at) => Self::parse_at(at).ok_or_else(|| bad_request("at"))?,
+            None => Utc::now(),
+        };
+        let limit = match params.get("limit") {
+            Some(limit) => limit
+                .parse()
+                .ok()
+                .filter(|limit| *limit <= MAX_LIMIT)
+                .ok_or_else(|| bad_request("limit"))?,
+            None => DEFAULT_LIMIT,
+        };
+        let window = match params.get("window") {
+            Some(window) => window
+                .parse()
+                .ok()
+                .filter(|window| (0..=MAX_WINDOW_MINUTES).contains(window))
+                .ok_or_else(|| bad_request("window"))?,
+            None => DEFAULT_WINDOW,
+        };
+
+        Ok(Query {
+            at,
+            limit,
+            look_ahead: Duration::minutes(window),
+            board: if flag("arrivals") {
+                Board::Arrivals
+            } else {
+                Board::Departures
+            },
+            show_all: flag("all"),
+        })
+    }
+
+    /// Reference time is either RFC 3339 or local time the same way as on
+    /// the command line.
+    fn parse_at(at: &str) -> Option<DateTime<Utc>> {
+        if let Ok(at) = DateTime::parse_from_rfc3339(at) {
+            return Some(at.with_timezone(&Utc));
+        }
+
+        Local
+            .from_local_datetime(&parse_datetime(at).ok()?)
+            .earliest()
+            .map(|at| at.with_timezone(&Utc))
+    }
+
+    fn internal(err: serde_json::Error) -> (StatusCode, String) {
+        (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+
+    fn json(status: StatusCode, body: String) -> Response<Body> {
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = status;
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        response
+    }
+
+    fn error(status: StatusCode, message: &str) -> Response<Body> {
+        let body = serde_json::json!({ "error": message }).to_string();
+
+        Self::json(status, body)
+    }
+}
+
+/// Decodes percent encoded path segment (stop IDs may contain spaces and
+/// such).
+fn percent_decode(segment: &str) -> String {
+    percent_encoding::percent_decode_str(segment)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    use transpors::config::Stop;
+    use transpors::db::Database;
+    use transpors::feed::Feed;
+
+    /// Timetables of the fixture feed stops OAK (configured in feeds "a"
+    /// and "b") and PARK (feed "a").
+    async fn timetables() -> Arc<RwLock<Timetables>> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/feed");
+        let feed = Feed::for_stops(&path, &["OAK", "PARK"]).unwrap();
+        let stop = |id: &str, feed_name: &str| Stop {
+            id: id.to_owned(),
+            name: feed.gtfs.stops[id].name.clone(),
+            feed: feed_name.to_owned(),
+            platform: None,
+            routes: vec![],
+            directions: vec![],
+            database: Database::from(&feed, &feed.gtfs.stops[id]).unwrap(),
+        };
+        let config = Config {
+            feeds: vec![],
+            stops: vec![stop("OAK", "a"), stop("OAK", "b"), stop("PARK", "a")],
+        };
+
+        Arc::new(RwLock::new(
+            Timetables::from(Arc::new(config)).await.unwrap(),
+        ))
+    }
+
+    /// Status and body of the GET request.
+    async fn get(uri: &str) -> (StatusCode, String) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = Server::handle(timetables().await, request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    const AT: &str = "at=2026-10-19T04:50:00Z";
+
+    #[tokio::test]
+    async fn departures() {
+        let (status, body) = get(&format!("/stops/PARK/departures?{}", AT)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("\"trip_id\":\"T1\""));
+    }
+
+    #[tokio::test]
+    async fn stop_of_more_feeds_needs_feed() {
+        let (status, body) = get(&format!("/stops/OAK/departures?{}", AT)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("give its feed"));
+
+        let (status, body) = get(&format!("/stops/OAK/departures?{}&feed=b", AT)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("\"feed\":\"b\""));
+
+        let (status, _) = get(&format!("/stops/OAK/departures?{}&feed=c", AT)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn limit_is_capped() {
+        for (limit, expected) in &[
+            ("1", StatusCode::OK),
+            ("100", StatusCode::OK),
+            ("101", StatusCode::BAD_REQUEST),
+            ("-1", StatusCode::BAD_REQUEST),
+            ("many", StatusCode::BAD_REQUEST),
+        ] {
+            let (status, body) =
+                get(&format!("/stops/PARK/departures?{}&limit={}", AT, limit)).await;
+
+            assert_eq!(status, *expected, "limit {}", limit);
+
+            if status == StatusCode::BAD_REQUEST {
+                assert_eq!(body, r#"{"error":"Invalid limit"}"#);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn window_is_within_range() {
+        for (window, expected) in &[
+            ("0", StatusCode::OK),
+            ("2880", StatusCode::OK),
+            ("2881", StatusCode::BAD_REQUEST),
+            ("-1", StatusCode::BAD_REQUEST),
+            ("", StatusCode::BAD_REQUEST),
+        ] {
+            let (status, body) =
+                get(&format!("/stops/PARK/departures?{}&window={}", AT, window)).await;
+
+            assert_eq!(status, *expected, "window {}", window);
+
+            if status == StatusCode::BAD_REQUEST {
+                assert_eq!(body, r#"{"error":"Invalid window"}"#);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn bad_requests() {
+        let (status, body) = get("/stops/PARK/departures?at=soon").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, r#"{"error":"Invalid at"}"#);
+
+        // Missing parameters have defaults.
+        let (status, _) = get("/stops/PARK/departures").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = get("/stops/DEPOT/departures").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = get("/departures").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let request = Request::post("/stops").body(Body::empty()).unwrap();
+        let response = Server::handle(timetables().await, request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
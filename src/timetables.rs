@@ -1,89 +1,326 @@
-use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, Weekday};
-use std::rc::Rc;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use crate::config::Config;
 use crate::config::Stop;
 use crate::db::Record;
+use crate::error::{Error, Result};
+use crate::realtime::{Prediction, Proximity, Realtime, ServiceAlert};
 
+/// How much a trip may be late to still show up among departures
+/// after its scheduled time has passed.
+const MAX_DELAY_MINUTES: i64 = 60;
+/// Longest look-ahead window (in minutes) departures are looked up for.
+pub const MAX_WINDOW_MINUTES: i64 = 48 * 60;
+
+/// Which stop times a board shows.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Board {
+    /// Departure times of trips passengers can board at the stop.
+    Departures,
+    /// Arrival times of trips passengers can alight from at the stop.
+    Arrivals,
+}
+
+/// Parameters for departures lookup.
+pub struct Query {
+    /// Only departures after this moment are returned.
+    pub at: DateTime<Utc>,
+    /// Maximum number of departures per stop.
+    pub limit: usize,
+    /// How far after `at` departures are looked up, at most
+    /// `MAX_WINDOW_MINUTES`.
+    pub look_ahead: Duration,
+    /// Whether departures or arrivals are looked up.
+    pub board: Board,
+    /// Includes also records passengers can't board (alight) at, e.g.
+    /// trips terminating at the stop.
+    pub show_all: bool,
+}
+
+/// Record resolved to a concrete departure time.
+pub struct NextDeparture<'a> {
+    /// Stop time of the trip at the stop.
+    pub record: &'a Record,
+    /// Service day the record belongs to (may be the day before `time`
+    /// for trips running after midnight).
+    pub service_date: NaiveDate,
+    /// Scheduled departure time in the agency timezone.
+    pub time: DateTime<Tz>,
+    /// Departure time predicted by realtime data.
+    pub realtime: Option<DateTime<Tz>>,
+    /// Trip (or its stop at the stop) is cancelled by realtime data.
+    pub cancelled: bool,
+    /// Where the vehicle serving the trip currently is.
+    pub proximity: Option<Proximity>,
+}
+
+impl<'a> NextDeparture<'a> {
+    /// Best known departure time - realtime if available, scheduled
+    /// otherwise.
+    pub fn expected_time(&self) -> DateTime<Tz> {
+        self.realtime.unwrap_or(self.time)
+    }
+
+    /// Difference between realtime and scheduled departure time.
+    pub fn delay(&self) -> Option<Duration> {
+        self.realtime.map(|realtime| realtime - self.time)
+    }
+}
+
+/// Whether the stop timetable covers the reference date.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeedStatus {
+    /// Service period includes the query date.
+    Valid,
+    /// Service period ended on the given date.
+    Expired(NaiveDate),
+    /// Service period starts on the given date.
+    NotYetValid(NaiveDate),
+}
+
+/// Next departures of one configured stop.
 pub struct Departure<'a> {
+    /// Configured stop the departures are from.
     pub stop: &'a Stop,
-    pub departures: Vec<&'a Record>,
+    /// Whether the feed of the stop is valid on the query date.
+    pub status: FeedStatus,
+    /// Alerts currently affecting the stop or its routes.
+    pub alerts: Vec<ServiceAlert>,
+    /// Departures ordered by their expected time.
+    pub departures: Vec<NextDeparture<'a>>,
 }
 
+/// Departures lookup over all configured stops.
 pub struct Timetables {
-    config: Rc<Config>,
+    config: Arc<Config>,
+    /// Feed name -> realtime data.
+    realtime: HashMap<String, Realtime>,
 }
 
 impl<'a> Timetables {
-    pub async fn from(config: Rc<Config>) -> Result<Self, Box<dyn std::error::Error>> {
-        let timetables = Timetables { config };
+    /// Creates timetables without realtime data, see `load_realtime`.
+    pub async fn from(config: Arc<Config>) -> Result<Self> {
+        let timetables = Timetables {
+            config,
+            realtime: HashMap::new(),
+        };
 
         Ok(timetables)
     }
 
-    pub fn get_departures(&self) -> Vec<Departure> {
+    /// Fetches realtime data of all feeds that have it configured. Feeds
+    /// the data can't be fetched for keep their previous data and their
+    /// errors are returned.
+    pub async fn load_realtime(&mut self) -> Vec<(String, String)> {
+        let (realtime, errors) = Self::fetch_realtime(&self.config).await;
+        self.set_realtime(realtime);
+
+        errors
+    }
+
+    /// Replaces realtime data of the given feeds.
+    pub fn set_realtime(&mut self, realtime: HashMap<String, Realtime>) {
+        self.realtime.extend(realtime);
+    }
+
+    /// Fetches realtime data without touching loaded timetables, so it
+    /// may run while others read them. Returns feed name -> realtime data
+    /// and errors of feeds that failed.
+    pub async fn fetch_realtime(
+        config: &Config,
+    ) -> (HashMap<String, Realtime>, Vec<(String, String)>) {
+        let mut fetched = HashMap::new();
+        let mut errors = vec![];
+
+        for feed in config.feeds.iter() {
+            let mut realtime = Realtime::default();
+            let mut loaded = false;
+
+            let urls = feed
+                .trip_updates_url
+                .iter()
+                .chain(feed.alerts_url.iter())
+                .chain(feed.vehicle_positions_url.iter());
+
+            for url in urls {
+                match realtime.load(url).await {
+                    Ok(()) => loaded = true,
+                    Err(err) => errors.push((feed.name.clone(), err.to_string())),
+                }
+            }
+
+            if loaded {
+                fetched.insert(feed.name.clone(), realtime);
+            }
+        }
+
+        (fetched, errors)
+    }
+
+    /// Configured stops.
+    pub fn stops(&self) -> &[Stop] {
+        &self.config.stops
+    }
+
+    /// Departures of all configured stops.
+    pub fn get_departures(&self, query: &Query) -> Vec<Departure<'_>> {
         let mut departures = vec![];
 
         for stop in self.config.stops.iter() {
-            departures.push(Departure {
-                stop,
-                departures: self.get_next_departures(&stop),
-            });
+            departures.push(self.get_stop_departures(stop, query));
         }
 
         departures
     }
 
+    /// Departures of the configured stop with the given ID. Feed has to
+    /// be given if stops of more feeds have the ID.
+    pub fn get_departures_by_id(
+        &self,
+        stop_id: &str,
+        feed: Option<&str>,
+        query: &Query,
+    ) -> Result<Departure<'_>> {
+        let mut stops = self
+            .config
+            .stops
+            .iter()
+            .filter(|s| s.id == stop_id && feed.is_none_or(|feed| s.feed == feed));
+
+        match (stops.next(), stops.next()) {
+            (Some(stop), None) => Ok(self.get_stop_departures(stop, query)),
+            (Some(_), Some(_)) => Err(Error::AmbiguousStop(stop_id.to_owned())),
+            (None, _) => Err(Error::UnknownStop(stop_id.to_owned())),
+        }
+    }
+
+    fn get_stop_departures(&self, stop: &'a Stop, query: &Query) -> Departure<'a> {
+        Departure {
+            stop,
+            status: self.get_feed_status(stop, query),
+            alerts: self
+                .realtime
+                .get(&stop.feed)
+                .map(|r| r.alerts(stop, query.at))
+                .unwrap_or_default(),
+            departures: self.get_next_departures(stop, query),
+        }
+    }
+
+    /// Checks the reference date against the stop service period.
+    fn get_feed_status(&self, stop: &Stop, query: &Query) -> FeedStatus {
+        let date = query
+            .at
+            .with_timezone(&stop.database.tz())
+            .date()
+            .naive_local();
+
+        match stop.database.service_period() {
+            Some(validity) if date > validity.end => FeedStatus::Expired(validity.end),
+            Some(validity) if date < validity.start => FeedStatus::NotYetValid(validity.start),
+            _ => FeedStatus::Valid,
+        }
+    }
+
     // TODO: async
-    fn get_next_departures(&self, stop: &'a Stop) -> Vec<&'a Record> {
-        // let now = Local::now();
-        // let date = now.date().naive_utc();
+    fn get_next_departures(&self, stop: &'a Stop, query: &Query) -> Vec<NextDeparture<'a>> {
+        let tz = stop.database.tz();
+        let realtime = self.realtime.get(&stop.feed);
+        let look_ahead = query
+            .look_ahead
+            .clamp(Duration::zero(), Duration::minutes(MAX_WINDOW_MINUTES));
+        let until = query.at + look_ahead;
+        let mut next_departures = vec![];
 
-        let now = NaiveDate::from_ymd(2020, 12, 6).and_hms(16, 0, 0);
-        let date = now.date();
+        // Delayed trips may still depart even if their scheduled time
+        // has already passed.
+        let since = match realtime {
+            Some(_) => query.at - Duration::minutes(MAX_DELAY_MINUTES),
+            None => query.at,
+        };
 
-        println!("{}", &now);
+        // Walk thru all service days the look-ahead window touches. Trips
+        // of the previous service day may still run after midnight.
+        let mut date = since.with_timezone(&tz).date().naive_local().pred();
 
-        let mut filtered_and_sorted = stop
-            .database
-            .records
-            .iter()
-            // Filter for date.
-            .filter(|r| {
-                if r.calendar.start_date <= date && r.calendar.end_date >= date {
-                    return true;
+        while date <= until.with_timezone(&tz).date().naive_local() {
+            let day_start = Self::service_day_start(&tz, date);
+
+            next_departures.extend(
+                stop.database
+                    .records
+                    .iter()
+                    // Filter for date (week days and calendar exceptions).
+                    .filter(|r| r.calendar.runs_on(date))
+                    // Filter for routes and directions the user watches.
+                    .filter(|r| stop.shows(r))
+                    // Filter out trips nobody can board (alight).
+                    .filter(|r| query.show_all || r.serves(query.board))
+                    .filter_map(|r| {
+                        r.stop_time(query.board).map(|stop_time| NextDeparture {
+                            record: r,
+                            service_date: date,
+                            time: day_start + Duration::seconds(stop_time.into()),
+                            realtime: None,
+                            cancelled: false,
+                            proximity: None,
+                        })
+                    })
+                    // Filter for the (extended) look-ahead window.
+                    .filter(|d| d.time > since && d.time <= until),
+            );
+
+            date = date.succ();
+        }
+
+        // Apply realtime predictions.
+        if let Some(realtime) = realtime {
+            for departure in next_departures.iter_mut() {
+                match realtime.predict(
+                    departure.record,
+                    departure.service_date,
+                    departure.time,
+                    query.board,
+                ) {
+                    Some(Prediction::At(time)) => departure.realtime = Some(time),
+                    Some(Prediction::Cancelled) => departure.cancelled = true,
+                    None => {}
                 }
 
-                false
-            })
-            // Filter for week day.
-            .filter(|r| match now.weekday() {
-                Weekday::Mon => r.calendar.monday,
-                Weekday::Tue => r.calendar.tuesday,
-                Weekday::Wed => r.calendar.wednesday,
-                Weekday::Thu => r.calendar.thursday,
-                Weekday::Fri => r.calendar.friday,
-                Weekday::Sat => r.calendar.saturday,
-                Weekday::Sun => r.calendar.sunday,
-                _ => false,
-            })
-            .collect::<Vec<&Record>>();
-
-        // Sort by stop time (arrival time).
-        filtered_and_sorted.sort_by_key(|r| r.stop_time);
-
-        filtered_and_sorted
-
-        // for record in filtered_and_sorted.iter() {
-        //     if record.stop_time.is_some() {
-        //         println!(
-        //             "{}",
-        //             NaiveDateTime::from_timestamp(record.stop_time.unwrap().into(), 0)
-        //                 .format("%H:%M")
-        //         );
-        //     }
-        // }
-
-        // println!("times: {}", filtered_and_sorted.len());
+                departure.proximity =
+                    realtime.proximity(departure.record, &stop.database, departure.service_date);
+            }
+        }
+
+        next_departures.retain(|d| d.expected_time() > query.at && d.expected_time() <= until);
+
+        // Sort by stop time.
+        next_departures.sort_by_key(|d| d.expected_time());
+
+        // Headway based trips are kept just once per line and direction.
+        let mut headway_lines = HashSet::new();
+        next_departures.retain(|d| {
+            d.record.headway.is_none()
+                || headway_lines.insert((&d.record.route, &d.record.headsign))
+        });
+
+        next_departures.truncate(query.limit);
+
+        next_departures
+    }
+
+    /// Returns the moment GTFS stop times of the given service day are
+    /// measured from - "noon minus 12h". It differs from midnight on days
+    /// when daylight saving time changes.
+    fn service_day_start(tz: &Tz, date: NaiveDate) -> DateTime<Tz> {
+        let noon = date.and_hms(12, 0, 0);
+
+        tz.from_local_datetime(&noon)
+            .earliest()
+            .unwrap_or_else(|| tz.from_utc_datetime(&noon))
+            - Duration::hours(12)
     }
 }
@@ -0,0 +1,328 @@
+use chrono::{DateTime, Local, Utc};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::{cursor, queue, terminal};
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crate::ui::Ui;
+use transpors::error::{Error, Result};
+use transpors::realtime::Proximity;
+use transpors::timetables::{Departure, NextDeparture, Query, Timetables};
+
+/// Width of the line column.
+const LINE_WIDTH: usize = 6;
+/// Width of the direction (headsign) column.
+const DIRECTION_WIDTH: usize = 28;
+/// Width of the countdown column.
+const COUNTDOWN_WIDTH: usize = 12;
+
+/// Full screen departure board redrawn every few seconds.
+pub struct Tui {
+    timetables: Timetables,
+    query: Query,
+    /// Real time the board started at together with the reference time
+    /// it started with - the reference time moves on with the clock.
+    started: (DateTime<Utc>, DateTime<Utc>),
+    interval: Duration,
+    realtime_interval: Option<Duration>,
+    /// Index of the stop shown.
+    selected: usize,
+    warnings: Vec<String>,
+}
+
+impl Tui {
+    /// Creates board. Realtime data are reloaded every `realtime_interval`
+    /// (never if `None`).
+    pub fn new(
+        timetables: Timetables,
+        query: Query,
+        interval: Duration,
+        realtime_interval: Option<Duration>,
+    ) -> Self {
+        Self {
+            started: (Utc::now(), query.at),
+            timetables,
+            query,
+            interval,
+            realtime_interval,
+            selected: 0,
+            warnings: vec![],
+        }
+    }
+
+    /// Runs the board until user quits. Terminal is restored however the
+    /// board ends, even if drawing fails or panics.
+    pub async fn run(&mut self) -> Result<()> {
+        let mut stdout = io::stdout();
+
+        terminal::enable_raw_mode().map_err(terminal_error)?;
+        let _restore = RestoreTerminal;
+        queue!(stdout, terminal::EnterAlternateScreen, cursor::Hide).map_err(terminal_error)?;
+
+        self.run_loop(&mut stdout).await
+    }
+
+    async fn run_loop(&mut self, stdout: &mut Stdout) -> Result<()> {
+        let mut realtime_loaded: Option<Instant> = None;
+
+        loop {
+            if let Some(realtime_interval) = self.realtime_interval {
+                if realtime_loaded.is_none_or(|loaded| loaded.elapsed() >= realtime_interval) {
+                    self.reload_realtime().await;
+                    realtime_loaded = Some(Instant::now());
+                }
+            }
+
+            self.query.at = self.started.1 + (Utc::now() - self.started.0);
+            self.draw(stdout)?;
+
+            // Wait for key press until the next redraw.
+            let redraw_at = Instant::now() + self.interval;
+
+            while let Some(timeout) = redraw_at.checked_duration_since(Instant::now()) {
+                if !event::poll(timeout).map_err(terminal_error)? {
+                    break;
+                }
+
+                if let Event::Key(key) = event::read().map_err(terminal_error)? {
+                    match self.handle_key(key) {
+                        Action::Quit => return Ok(()),
+                        Action::Redraw => break,
+                        Action::ReloadRealtime => {
+                            realtime_loaded = None;
+                            break;
+                        }
+                        Action::None => {}
+                    }
+                }
+            }
+        }
+    }
+
+    async fn reload_realtime(&mut self) {
+        self.warnings = self
+            .timetables
+            .load_realtime()
+            .await
+            .into_iter()
+            .map(|(feed, err)| format!("Realtime data of feed {} unavailable: {}", feed, err))
+            .collect();
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        let stops = self.timetables.stops().len().max(1);
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+            KeyCode::Char('r') if self.realtime_interval.is_some() => Action::ReloadRealtime,
+            KeyCode::Right | KeyCode::Tab | KeyCode::Char('l') => {
+                self.selected = (self.selected + 1) % stops;
+                Action::Redraw
+            }
+            KeyCode::Left | KeyCode::BackTab | KeyCode::Char('h') => {
+                self.selected = (self.selected + stops - 1) % stops;
+                Action::Redraw
+            }
+            KeyCode::Char(c) if c.is_ascii_digit() && c != '0' => {
+                let index = c.to_digit(10).unwrap_or(1) as usize - 1;
+
+                if index < stops {
+                    self.selected = index;
+                }
+
+                Action::Redraw
+            }
+            _ => Action::None,
+        }
+    }
+
+    fn draw(&self, stdout: &mut Stdout) -> Result<()> {
+        let (width, height) = terminal::size().map_err(terminal_error)?;
+        let (width, height) = (width as usize, height as usize);
+        let departures = self.timetables.get_departures(&self.query);
+        let mut lines: Vec<String> = vec![];
+
+        queue!(stdout, terminal::Clear(terminal::ClearType::All)).map_err(terminal_error)?;
+
+        // Stop tabs with clock.
+        let tabs: Vec<String> = departures
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                if i == self.selected {
+                    format!("[{}]", d.stop.display_name())
+                } else {
+                    format!(" {} ", d.stop.display_name())
+                }
+            })
+            .collect();
+        let clock = self
+            .query
+            .at
+            .with_timezone(&Local)
+            .format("%H:%M:%S")
+            .to_string();
+        let tabs = tabs.join(" ");
+        let padding = width.saturating_sub(tabs.chars().count() + clock.chars().count());
+
+        queue!(
+            stdout,
+            cursor::MoveTo(0, 0),
+            SetAttribute(Attribute::Reverse),
+            Print(fit(
+                &format!("{}{}{}", tabs, " ".repeat(padding), clock),
+                width
+            )),
+            SetAttribute(Attribute::Reset)
+        )
+        .map_err(terminal_error)?;
+
+        lines.extend(self.warnings.iter().cloned());
+
+        if let Some(departure) = departures.get(self.selected) {
+            lines.extend(Self::notices(departure));
+            lines.push(String::new());
+            lines.push(format!(
+                "{}{}{}{}",
+                pad("LINE", LINE_WIDTH),
+                pad("DIRECTION", DIRECTION_WIDTH),
+                pad("DEPARTS", COUNTDOWN_WIDTH),
+                "TIME"
+            ));
+
+            for next_departure in departure.departures.iter() {
+                lines.push(self.format_row(next_departure));
+            }
+
+            if departure.departures.is_empty() {
+                lines.push("No departures.".to_string());
+            }
+        } else {
+            lines.push("No stops configured.".to_string());
+        }
+
+        // Leave the last line for help.
+        for (i, line) in lines.iter().take(height.saturating_sub(2)).enumerate() {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, i as u16 + 1),
+                Print(fit(line, width))
+            )
+            .map_err(terminal_error)?;
+        }
+
+        let help = if self.realtime_interval.is_some() {
+            "←/→ switch stop  1-9 jump to stop  r reload realtime  q quit"
+        } else {
+            "←/→ switch stop  1-9 jump to stop  q quit"
+        };
+
+        queue!(
+            stdout,
+            cursor::MoveTo(0, height.saturating_sub(1) as u16),
+            SetAttribute(Attribute::Dim),
+            Print(fit(help, width)),
+            SetAttribute(Attribute::Reset)
+        )
+        .map_err(terminal_error)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    /// Timetable validity warning and alert headers of the stop.
+    fn notices(departure: &Departure) -> Vec<String> {
+        Ui::format_status(departure)
+            .into_iter()
+            .chain(
+                departure
+                    .alerts
+                    .iter()
+                    .map(|a| format!("Alert: {}", a.header)),
+            )
+            .collect()
+    }
+
+    fn format_row(&self, next_departure: &NextDeparture) -> String {
+        let record = next_departure.record;
+        let headsign = record
+            .headsign
+            .as_deref()
+            .unwrap_or(&record.route_long_name);
+        let seconds =
+            (next_departure.expected_time().with_timezone(&Utc) - self.query.at).num_seconds();
+
+        let countdown = match record.headway {
+            _ if next_departure.cancelled => "cancelled".to_string(),
+            Some(headway) => format!("every {} min", headway.div_ceil(60)),
+            None if seconds < 60 => "now".to_string(),
+            None => format!("{} min", seconds / 60),
+        };
+
+        let mut time = next_departure.expected_time().format("%H:%M").to_string();
+
+        if let Some(delay) = next_departure.delay() {
+            match delay.num_minutes() {
+                0 => time.push_str(" on time"),
+                delay => time.push_str(&format!(" {:+}", delay)),
+            }
+        }
+
+        match next_departure.proximity {
+            Some(Proximity::AtStop) => time.push_str(", at stop"),
+            Some(Proximity::Arriving) => time.push_str(", arriving"),
+            Some(Proximity::StopsAway(1)) => time.push_str(", 1 stop away"),
+            Some(Proximity::StopsAway(n)) => time.push_str(&format!(", {} stops away", n)),
+            None => {}
+        }
+
+        format!(
+            "{}{}{}{}",
+            pad(&record.route, LINE_WIDTH),
+            pad(headsign, DIRECTION_WIDTH),
+            pad(&countdown, COUNTDOWN_WIDTH),
+            time
+        )
+    }
+}
+
+/// What to do after key press.
+enum Action {
+    Quit,
+    Redraw,
+    ReloadRealtime,
+    None,
+}
+
+/// Leaves the alternate screen and raw mode when dropped. Errors are
+/// ignored, there is nothing better to do with the terminal anyway.
+struct RestoreTerminal;
+
+impl Drop for RestoreTerminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+
+        let _ = queue!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Wraps crossterm failure.
+fn terminal_error(err: crossterm::ErrorKind) -> Error {
+    Error::Terminal(err.to_string())
+}
+
+/// Pads (or cuts) text to the column width, keeping one space gap.
+fn pad(text: &str, width: usize) -> String {
+    let text = fit(text, width - 1);
+
+    format!("{}{}", text, " ".repeat(width - text.chars().count()))
+}
+
+/// Cuts text to the screen width.
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
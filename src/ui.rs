@@ -1,118 +1,95 @@
 use std::io::{self, prelude::*, BufRead};
-use std::path::PathBuf;
+use std::path::Path;
 use std::sync::Arc;
 
-use chrono::NaiveDateTime;
-use gtfs_structures::{Gtfs, Stop};
+use gtfs_structures::Stop;
 use rayon::prelude::*;
-use tokio::fs;
 
-use crate::config::Config;
+use transpors::config::{Config, DataFileVersion, DEFAULT_FEED};
+use transpors::error::{Error, Result};
 // use crate::db::Record;
-use crate::timetables::Departure;
+use transpors::config::Stop as ConfigStop;
+use transpors::db::{Database, RouteDirection};
+use transpors::feed::Feed;
+use transpors::realtime::{Proximity, ServiceAlert};
+use transpors::timetables::{Departure, FeedStatus, NextDeparture};
+
+/// Maximum number of stops offered for one search.
+const MAX_FOUND_STOPS: usize = 20;
+
+/// Stop ID together with the stop itself.
+pub type FoundStop = (String, Arc<Stop>);
 
 pub struct WizardOutput {
-    pub gtfs: Gtfs,
-    pub stops: Vec<(String, Arc<Stop>)>,
+    /// Chosen stops of the default feed with their databases built.
+    pub stops: Vec<ConfigStop>,
 }
 
 /// Wizard for user that ask a few questions.
 /// The result is used by Config struct.
 pub struct Wizard<'a> {
     pub data_file_url: Option<String>,
-    conf_dir: &'a PathBuf,
-    pub data_file_path: Option<PathBuf>,
+    pub data_file_version: Option<DataFileVersion>,
+    data_file_path: &'a Path,
 }
 
 impl<'a> Wizard<'a> {
-    pub async fn new(conf_dir: &'a PathBuf) -> Wizard<'a> {
+    /// Creates wizard that stores data file to the given path.
+    pub async fn new(data_file_path: &'a Path) -> Wizard<'a> {
         Wizard {
             data_file_url: None,
-            conf_dir,
-            data_file_path: None,
+            data_file_version: None,
+            data_file_path,
         }
     }
 
-    pub async fn run_wizard(&mut self) -> Result<WizardOutput, Box<dyn std::error::Error>> {
-        let gtfs = self.retrieve_data_file().await?;
-        let stops = self.read_stop_names(&gtfs)?;
+    pub async fn run_wizard(&mut self) -> Result<WizardOutput> {
+        let feed = self.retrieve_data_file().await?;
+        let stops = self.read_stop_names(&feed)?;
         // let times = self.read_stop_times(&gtfs, &stops)?;
 
-        Ok(WizardOutput { gtfs, stops })
+        Ok(WizardOutput { stops })
         // Ok(WizardOutput { gtfs, stops, times })
     }
 
     /// Downloads or copies (depends on the origin location) the datafile
     /// to project config location (see Config.path).
-    async fn retrieve_data_file(&mut self) -> Result<Gtfs, Box<dyn std::error::Error>> {
+    async fn retrieve_data_file(&mut self) -> Result<Feed> {
         // Read data file path/URL.
         println!("Enter data file path/URL: ");
-        let mut data_file = String::new();
-        io::stdin().lock().read_line(&mut data_file)?;
-        data_file = data_file.trim().to_owned();
+        let data_file = read_line()?;
         self.data_file_url = Some(data_file.clone());
 
         // Download or copy data file.
-        self.data_file_path = Some(
-            self.download_or_copy_data_file(self.conf_dir, data_file)
-                .await?,
-        );
+        print!("Retrieving data file ...");
+        io::stdout().flush()?;
+        self.data_file_version =
+            Config::download_or_copy_data_file(self.data_file_path, &data_file, None).await?;
+        println!(" done!");
 
         print!("Parsing ...");
-        io::stdout().flush().unwrap();
-        let gtfs = Gtfs::new(self.data_file_path.clone().unwrap().to_str().unwrap())?;
+        io::stdout().flush()?;
+        let feed = Feed::for_stops(self.data_file_path, &[])?;
         println!(" done!");
 
-        Ok(gtfs)
-    }
-
-    /// Downloads or copies the data file into config folder.
-    async fn download_or_copy_data_file(
-        &self,
-        conf_dir: &PathBuf,
-        path_or_url: String,
-    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
-        // Prepare data file path.
-        let mut data_file_path = conf_dir.clone();
-        data_file_path.push("data_file.gtfs");
-
-        // Download or copy.
-        if path_or_url.starts_with("http") {
-            print!("Downloading ...");
-            io::stdout().flush()?;
-            fs::write(
-                &data_file_path,
-                reqwest::get(&path_or_url).await?.bytes().await?,
-            )
-            .await?;
-            println!(" done!");
-        } else {
-            fs::copy(&path_or_url, &data_file_path).await?;
-        }
-
-        Ok(data_file_path)
+        Ok(feed)
     }
 
     /// Triggers the loop for reading stop names. User can
     /// enter as many stops as he likes.
-    fn read_stop_names(
-        &self,
-        gtfs: &'a Gtfs,
-    ) -> Result<Vec<(String, Arc<Stop>)>, Box<dyn std::error::Error>> {
+    fn read_stop_names(&self, feed: &Feed) -> Result<Vec<ConfigStop>> {
         let mut chosen_stops = vec![];
 
         loop {
             // Read stop name.
-            chosen_stops.push(self.read_stop_name(gtfs)?);
+            chosen_stops.push(self.read_stop_name(feed)?);
 
             // Ask for more stops.
             println!(
                 "Currently {} stop(s) has been chosen. Do you want to add another one? (y/n)",
                 chosen_stops.len()
             );
-            let mut answer = String::new();
-            io::stdin().lock().read_line(&mut answer).unwrap();
-            answer = answer.trim().to_owned();
+            let answer = read_line()?;
 
             if "y" != answer.to_lowercase() {
                 break;
@@ -122,89 +99,124 @@ impl<'a> Wizard<'a> {
         Ok(chosen_stops)
     }
 
-    /// Tries to collect one stop based on user input.
-    fn read_stop_name(
-        &self,
-        gtfs: &'a Gtfs,
-    ) -> Result<(String, Arc<Stop>), Box<dyn std::error::Error>> {
-        loop {
-            let mut found_stops = self.seek_stops(gtfs)?;
-
-            println!("Found {} stops:", found_stops.len());
-
-            // Sort found stops by stop name.
-            found_stops.sort_by_key(|i| i.1.name.clone());
-
-            // Paralelly iterate thru stops and fetch terminating station for
-            // each station (based on trip direction).
-            let found_stops_with_terminating_stop: Vec<(String, Arc<Stop>, Arc<Stop>)> =
-                found_stops
-                    .par_iter()
-                    .map(|item| {
-                        (
-                            item.0.clone(),
-                            item.1.clone(),
-                            self.get_last_trip_stop_for_stop(gtfs, item.1.clone()),
-                        )
-                    })
-                    .collect();
-
-            // Print stops.
-            for (i, (_, stop, terminating_stop)) in
-                found_stops_with_terminating_stop.iter().enumerate()
-            {
-                println!("{}) {} -> {}", i, stop, terminating_stop);
-            }
+    /// Tries to collect one stop based on user input. Its database is
+    /// built right away, stop times of the found stops are read anyway to
+    /// list their directions.
+    fn read_stop_name(&self, feed: &Feed) -> Result<ConfigStop> {
+        let found_stops = self.seek_stops(feed)?;
+        let found_stops: Vec<Arc<Stop>> = found_stops.into_iter().map(|s| s.1).collect();
+
+        // Platforms are listed under their station, the station itself
+        // stands for all of its platforms.
+        let mut choices: Vec<(Arc<Stop>, bool)> = vec![];
 
-            // Read stop number.
-            let stop_number: usize;
-
-            loop {
-                println!("Please enter the number of stop you want to choose:");
-                let mut stop_number_input = String::new();
-                io::stdin().lock().read_line(&mut stop_number_input)?;
-
-                // Validate stop number.
-                match stop_number_input.trim().parse::<usize>() {
-                    Ok(number) => {
-                        stop_number = number;
-                        break;
-                    }
-                    Err(_) => {
-                        println!("Wrong number! Try again.");
-                    }
+        for (station, platforms) in feed.group_by_station(&found_stops) {
+            choices.push((station, false));
+            choices.extend(platforms.into_iter().map(|p| (p, true)));
+        }
+
+        println!("Found {} stops:", choices.len());
+
+        let stop_ids: Vec<&str> = choices.iter().map(|c| c.0.id.as_str()).collect();
+        let found_feed = Feed::for_stops(self.data_file_path, &stop_ids)?;
+
+        // Paralelly build databases of all found stops.
+        let mut databases: Vec<Database> = choices
+            .par_iter()
+            .map(|(stop, _)| Database::from(&found_feed, stop))
+            .collect::<Result<_>>()?;
+
+        // Print stops.
+        for (i, ((stop, is_platform), database)) in choices.iter().zip(databases.iter()).enumerate()
+        {
+            let name = if Feed::is_station(stop) {
+                let platforms = found_feed.platforms(&stop.id).len();
+
+                format!("{} (whole station, {} platforms)", stop, platforms)
+            } else {
+                match (is_platform, found_feed.platform_code(&stop.id)) {
+                    (true, Some(code)) => format!("   platform {}", code),
+                    (true, None) => format!("   {}", stop.id),
+                    (false, _) => stop.to_string(),
                 }
-            }
+            };
+            let directions: Vec<RouteDirection> = database.directions().into_iter().collect();
+
+            println!("{}) {}: {}", i, name, format_directions(&directions));
+        }
 
-            if let Some(stop) = found_stops.get(stop_number) {
-                return Ok(stop.clone());
+        // Read stop number.
+        let stop_number = loop {
+            let number = read_number("Please enter the number of stop you want to choose:")?;
+
+            if number < choices.len() {
+                break number;
             }
 
             println!("Wrong number! Try again.");
+        };
+
+        let stop = &choices[stop_number].0;
+        let database = databases.swap_remove(stop_number);
+        let directions: Vec<RouteDirection> = database.directions().into_iter().collect();
+
+        Ok(ConfigStop {
+            id: stop.id.clone(),
+            name: stop.name.clone(),
+            feed: DEFAULT_FEED.to_owned(),
+            platform: found_feed.platform_code(&stop.id).map(str::to_owned),
+            routes: vec![],
+            directions: self.read_directions(&directions)?,
+            database,
+        })
+    }
+
+    /// Lets user pick directions (served from the chosen stop) whose
+    /// departures are shown. Returns empty list for all of them.
+    fn read_directions(&self, directions: &[RouteDirection]) -> Result<Vec<RouteDirection>> {
+        if directions.len() < 2 {
+            return Ok(vec![]);
+        }
+
+        println!("Directions served from the stop:");
+
+        for (i, direction) in directions.iter().enumerate() {
+            println!("{}) {}", i, direction);
+        }
+
+        loop {
+            println!("Enter numbers of directions to show separated by spaces (nothing for all):");
+            let input = read_line()?;
+
+            let chosen: Option<Vec<RouteDirection>> = input
+                .split_whitespace()
+                .map(|n| n.parse::<usize>().ok().and_then(|n| directions.get(n)))
+                .map(|d| d.cloned())
+                .collect();
+
+            match chosen {
+                Some(chosen) => return Ok(chosen),
+                None => println!("Wrong number! Try again."),
+            }
         }
     }
 
     /// Asks user for input and then finds similar stops in datafile.
     /// All similar stops are then returned.
     /// If no similar stop are found user is asked for the input again.
-    fn seek_stops(
-        &self,
-        gtfs: &'a Gtfs,
-    ) -> Result<Vec<(String, Arc<Stop>)>, Box<dyn std::error::Error>> {
-        let mut found_stops: Vec<(String, Arc<Stop>)>;
+    fn seek_stops(&self, feed: &Feed) -> Result<Vec<FoundStop>> {
+        let mut found_stops: Vec<FoundStop>;
 
         loop {
             println!("Enter stop name: ");
-            let mut stop = String::new();
-            io::stdin().lock().read_line(&mut stop)?;
-            stop = stop.trim().to_owned();
-
-            // Validate stop name against data file.
-            found_stops = gtfs
-                .stops
-                .iter()
-                .filter(|x| x.1.name.contains(stop.as_str()))
-                .map(|x| (x.0.clone(), x.1.clone()))
+            let stop = read_line()?;
+
+            // Validate stop name against data file (most relevant first).
+            found_stops = feed
+                .search_stops(&stop)
+                .into_iter()
+                .take(MAX_FOUND_STOPS)
+                .map(|(stop, _)| (stop.id.clone(), stop))
                 .collect();
 
             // We did found at least one stop.
@@ -217,66 +229,195 @@ impl<'a> Wizard<'a> {
 
         Ok(found_stops)
     }
+}
 
-    /// Seeks last stop (terminating station) for the given stop (based on
-    /// associated trip and stop times.
-    fn get_last_trip_stop_for_stop(&self, gtfs: &'a Gtfs, stop: Arc<Stop>) -> Arc<Stop> {
-        let mut found_stop: Option<Arc<Stop>> = None;
-
-        // Closes thing to stops we have are trips.
-        for (_, trip) in gtfs.trips.iter() {
-            for time in trip.stop_times.iter() {
-                if time.stop.id == stop.id {
-                    found_stop = Some(
-                        trip.stop_times
-                            .last()
-                            .expect("Trip ha no stop - that's very weird!")
-                            .stop
-                            .clone(),
-                    );
-                }
-            }
-        }
+/// Reads one trimmed line from stdin. End of input is an error so the
+/// wizard doesn't ask forever.
+fn read_line() -> Result<String> {
+    let mut line = String::new();
 
-        if found_stop.is_none() {
-            return stop;
-        }
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(Error::InvalidInput(
+            "Unexpected end of input, the wizard needs a terminal".to_string(),
+        ));
+    }
+
+    Ok(line.trim().to_owned())
+}
+
+/// Asks for a number until a valid one is entered.
+fn read_number(prompt: &str) -> Result<usize> {
+    loop {
+        println!("{}", prompt);
 
-        found_stop.unwrap()
+        match read_line()?.parse::<usize>() {
+            Ok(number) => return Ok(number),
+            Err(_) => println!("Wrong number! Try again."),
+        }
     }
 }
 
-pub struct Ui<'a> {
-    config: &'a Config,
+/// Comma separated route directions, e.g. "12 → Depot, 22 → Airport".
+fn format_directions(directions: &[RouteDirection]) -> String {
+    if directions.is_empty() {
+        return "no departures".to_owned();
+    }
+
+    directions
+        .iter()
+        .map(RouteDirection::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
-impl<'a> Ui<'a> {
-    pub fn new(config: &'a Config) -> Self {
-        Self { config }
+pub struct Ui;
+
+impl Ui {
+    pub fn new() -> Self {
+        Self
     }
 
     pub fn output(&self, departures: Vec<Departure>) {
         for departure in departures.iter() {
             // Heading.
-            let stop_name = &departures.first().unwrap().stop.name;
+            let stop_name = departure.stop.display_name();
             println!("{}", stop_name);
             println!("{}", "-".repeat(stop_name.chars().count()));
 
+            if let Some(warning) = Self::format_status(departure) {
+                println!("{}", warning);
+            }
+
+            for alert in departure.alerts.iter() {
+                println!("{}", Self::format_alert(alert));
+            }
+
             // Timetable.
-            for departure_record in departure.departures.iter() {
-                if departure_record.stop_time.is_some() {
-                    println!(
-                        "{} {} {}",
-                        departure_record.route,
-                        departure_record.trip,
-                        NaiveDateTime::from_timestamp(
-                            departure_record.stop_time.unwrap().into(),
-                            0
-                        )
-                        .format("%H:%M")
-                    );
-                }
+            for next_departure in departure.departures.iter() {
+                println!("{}", Self::format_departure(departure.stop, next_departure));
             }
         }
     }
+
+    /// Outputs departures from all stops as one board sorted by time.
+    pub fn output_merged(&self, departures: Vec<Departure>, limit: usize) {
+        for warning in departures.iter().filter_map(Self::format_status) {
+            println!("{}", warning);
+        }
+
+        // The same alert may affect more stops.
+        let mut alerts: Vec<String> = departures
+            .iter()
+            .flat_map(|d| d.alerts.iter().map(Self::format_alert))
+            .collect();
+        alerts.sort();
+        alerts.dedup();
+
+        for alert in alerts {
+            println!("{}", alert);
+        }
+
+        let mut merged: Vec<(&ConfigStop, &NextDeparture)> = departures
+            .iter()
+            .flat_map(|d| d.departures.iter().map(move |n| (d.stop, n)))
+            .collect();
+        merged.sort_by_key(|(_, n)| n.expected_time());
+
+        for (stop, next_departure) in merged.into_iter().take(limit) {
+            println!(
+                "{} ({})",
+                Self::format_departure(stop, next_departure),
+                stop.display_name()
+            );
+        }
+    }
+
+    /// Formats warning about timetable not covering the reference date.
+    pub fn format_status(departure: &Departure) -> Option<String> {
+        let problem = match departure.status {
+            FeedStatus::Valid => return None,
+            FeedStatus::Expired(end) => format!("expired on {}", end),
+            FeedStatus::NotYetValid(start) => format!("is valid from {}", start),
+        };
+
+        Some(format!(
+            "Warning: timetable of {} {}, run \"transpors update {}\".",
+            departure.stop.display_name(),
+            problem,
+            departure.stop.feed
+        ))
+    }
+
+    /// Formats alert banner - header followed by indented description
+    /// and link.
+    fn format_alert(alert: &ServiceAlert) -> String {
+        let mut banner = format!("Alert: {}", alert.header);
+
+        for line in alert.description.iter().chain(alert.url.iter()) {
+            banner.push_str(&format!("\n  {}", line.trim().replace('\n', "\n  ")));
+        }
+
+        banner
+    }
+
+    /// Formats departure time. Realtime times are marked as live and
+    /// show the delay.
+    fn format_time(next_departure: &NextDeparture) -> String {
+        let scheduled = next_departure.time.format("%H:%M");
+
+        if next_departure.cancelled {
+            return format!("{} cancelled", scheduled);
+        }
+
+        match next_departure.delay() {
+            Some(delay) if delay.num_minutes() != 0 => format!(
+                "{} ({:+} min, live)",
+                next_departure.expected_time().format("%H:%M"),
+                delay.num_minutes()
+            ),
+            Some(_) => format!("{} (live)", scheduled),
+            None => scheduled.to_string(),
+        }
+    }
+
+    /// Formats one board line. Departures of a station tell the platform.
+    fn format_departure(stop: &ConfigStop, next_departure: &NextDeparture) -> String {
+        let record = next_departure.record;
+        let headsign = record
+            .headsign
+            .as_deref()
+            .unwrap_or(&record.route_long_name);
+
+        let time = match record.headway {
+            Some(headway) => format!("every {} min", headway.div_ceil(60)),
+            None => Self::format_time(next_departure),
+        };
+
+        let proximity = match next_departure.proximity {
+            Some(Proximity::AtStop) => " (at stop)".to_string(),
+            Some(Proximity::Arriving) => " (arriving)".to_string(),
+            Some(Proximity::StopsAway(1)) => " (1 stop away)".to_string(),
+            Some(Proximity::StopsAway(n)) => format!(" ({} stops away)", n),
+            None => "".to_string(),
+        };
+
+        let platform = match &record.platform {
+            Some(platform) if stop.platform.is_none() => format!(" (platform {})", platform),
+            _ => "".to_string(),
+        };
+
+        format!(
+            "Line {} → {} {}{}{}{}",
+            record.route,
+            headsign,
+            time,
+            platform,
+            proximity,
+            if record.last_stop {
+                " (terminates here)"
+            } else {
+                ""
+            }
+        )
+    }
 }
@@ -0,0 +1,41 @@
+mod common;
+
+use std::sync::Arc;
+
+use transpors::cache::Cache;
+use transpors::db::Database;
+
+use common::feed;
+
+const SHA256: &str = "fixture";
+
+#[test]
+fn decoded_databases_match_encoded() {
+    let feed = feed(&["MAIN"]);
+    let database = Database::from(&feed, &feed.gtfs.stops["MAIN"]).unwrap();
+
+    let content = Cache::encode(SHA256, vec![("MAIN", &database)]).unwrap();
+    let decoded = Cache::decode(&content, SHA256).unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&decoded["MAIN"]).unwrap(),
+        serde_json::to_value(&database).unwrap()
+    );
+    // Data file changed.
+    assert!(Cache::decode(&content, "other").is_none());
+}
+
+#[test]
+fn decoded_records_share_routes() {
+    let feed = feed(&["MAIN"]);
+    let database = Database::from(&feed, &feed.gtfs.stops["MAIN"]).unwrap();
+
+    let content = Cache::encode(SHA256, vec![("MAIN", &database)]).unwrap();
+    let decoded = Cache::decode(&content, SHA256).unwrap();
+    let records = &decoded["MAIN"].records;
+
+    assert!(records.len() > 1);
+    assert!(records
+        .iter()
+        .all(|r| Arc::ptr_eq(&r.route_long_name, &records[0].route_long_name)));
+}
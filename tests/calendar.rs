@@ -0,0 +1,33 @@
+//! Which days trips run on, given by calendars and calendar dates.
+
+mod common;
+
+use common::{departures, trips};
+
+#[tokio::test]
+async fn removed_and_added_dates() {
+    // Wednesday holiday runs the weekend service instead of the weekday
+    // one.
+    let holiday = departures("OAK", "2026-10-28 06:56").await;
+    let wednesday = departures("OAK", "2026-10-21 06:56").await;
+
+    assert_eq!(trips(&holiday), vec!["T2"]);
+    assert_eq!(trips(&wednesday), vec!["T1"]);
+}
+
+#[tokio::test]
+async fn service_given_by_calendar_dates_only() {
+    let christmas_eve = departures("OAK", "2026-12-24 08:50").await;
+    let christmas = departures("OAK", "2026-12-25 08:50").await;
+
+    assert_eq!(trips(&christmas_eve), vec!["T3"]);
+    assert!(trips(&christmas).is_empty());
+}
+
+#[tokio::test]
+async fn service_with_removed_dates_only_is_skipped() {
+    // GHOST never runs, the rest of the stop still does.
+    let monday = departures("PARK", "2026-10-19 07:00").await;
+
+    assert_eq!(trips(&monday), vec!["T1"]);
+}
@@ -0,0 +1,162 @@
+//! Helpers shared by the integration tests, which run against the small
+//! GTFS data file (and realtime snapshots) in `tests/fixtures`.
+
+// Each test binary uses just some of them.
+#![allow(dead_code)]
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Europe::Prague;
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use zip::write::{FileOptions, ZipWriter};
+
+use transpors::config::{Config, Stop};
+use transpors::db::Database;
+use transpors::feed::Feed;
+use transpors::timetables::{Board, Query, Timetables};
+
+/// Feed name stops of the fixture feed are configured with.
+pub const FEED: &str = "fixture";
+
+/// Path of the given fixture file (or directory).
+pub fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Fixture feed zipped into the given file, as data files are retrieved.
+pub fn zip_feed(path: &Path) {
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+
+    for entry in fs::read_dir(fixture("feed")).unwrap() {
+        let entry = entry.unwrap();
+        zip.start_file(entry.file_name().to_str().unwrap(), FileOptions::default())
+            .unwrap();
+        zip.write_all(&fs::read(entry.path()).unwrap()).unwrap();
+    }
+
+    zip.finish().unwrap();
+}
+
+/// Config and cache directories are taken from the environment shared
+/// by all tests of a binary, so tests using them run one at a time.
+static HOME_LOCK: Mutex<()> = Mutex::new(());
+
+/// Temporary config and cache directories of a test, removed once
+/// dropped.
+pub struct Home {
+    pub dir: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Home {
+    /// Config directory of transpors.
+    pub fn conf_dir(&self) -> PathBuf {
+        self.dir.join("config/transpors")
+    }
+
+    /// Cache directory of transpors.
+    pub fn cache_dir(&self) -> PathBuf {
+        self.dir.join("cache/transpors")
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Points config and cache directories to empty temporary ones.
+pub fn home(test: &str) -> Home {
+    // A failed test doesn't stop the others.
+    let lock = HOME_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let dir = env::temp_dir().join(format!("transpors-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    env::set_var("XDG_CONFIG_HOME", dir.join("config"));
+    env::set_var("XDG_CACHE_HOME", dir.join("cache"));
+
+    Home { dir, _lock: lock }
+}
+
+/// Fixture feed parsed for the given stops.
+pub fn feed(stop_ids: &[&str]) -> Feed {
+    Feed::for_stops(&fixture("feed"), stop_ids).unwrap()
+}
+
+/// Configured stop with its database built from the feed.
+pub fn stop(feed: &Feed, stop_id: &str) -> Stop {
+    let gtfs_stop = &feed.gtfs.stops[stop_id];
+
+    Stop {
+        id: gtfs_stop.id.clone(),
+        name: gtfs_stop.name.clone(),
+        feed: FEED.to_owned(),
+        platform: feed.platform_code(stop_id).map(str::to_owned),
+        routes: vec![],
+        directions: vec![],
+        database: Database::from(feed, gtfs_stop).unwrap(),
+    }
+}
+
+/// Config (without feeds) watching the given stops.
+pub fn config(stops: Vec<Stop>) -> Arc<Config> {
+    Arc::new(Config {
+        feeds: vec![],
+        stops,
+    })
+}
+
+/// Departures query at the given local (feed timezone) time, given as
+/// "YYYY-MM-DD HH:MM".
+pub fn query(at: &str) -> Query {
+    let at = NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M").unwrap();
+
+    Query {
+        at: Prague
+            .from_local_datetime(&at)
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc),
+        limit: 10,
+        look_ahead: Duration::hours(2),
+        board: Board::Departures,
+        show_all: false,
+    }
+}
+
+/// Trip ID, service day and local departure time ("HH:MM") of departures
+/// from the stop at the given local time.
+pub async fn departures(stop_id: &str, at: &str) -> Vec<(String, NaiveDate, String)> {
+    let feed = feed(&[stop_id]);
+    let timetables = Timetables::from(config(vec![stop(&feed, stop_id)]))
+        .await
+        .unwrap();
+
+    timetables.get_departures(&query(at))[0]
+        .departures
+        .iter()
+        .map(|d| {
+            (
+                d.record.trip.clone(),
+                d.service_date,
+                d.expected_time().format("%H:%M").to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Trip IDs of the departures.
+pub fn trips(departures: &[(String, NaiveDate, String)]) -> Vec<&str> {
+    departures.iter().map(|d| d.0.as_str()).collect()
+}
+
+/// Date given as "YYYY-MM-DD".
+pub fn date(date: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+}
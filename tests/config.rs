@@ -0,0 +1,299 @@
+mod common;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use transpors::config::{Config, DataFileVersion, FeedSource, FeedUpdate, DEFAULT_FEED};
+use transpors::db::RouteDirection;
+use transpors::error::Error;
+
+use common::{feed, fixture, home, stop, zip_feed};
+
+#[tokio::test]
+async fn legacy_config_is_migrated_to_default_feed() {
+    let home = home("legacy");
+    let conf_dir = home.conf_dir();
+    fs::create_dir_all(&conf_dir).unwrap();
+
+    // Layout from before feeds, with databases kept in the config file.
+    let legacy = format!(
+        "---\ndata_file_url: https://example.com/gtfs.zip\ndata_file_path: {}\nstops:\n  - id: OAK\n    name: Oak Street\n    database:\n      records: []\n",
+        fixture("feed").display()
+    );
+    fs::write(conf_dir.join("config.yaml"), legacy).unwrap();
+
+    let config = Config::load().await.unwrap().unwrap();
+
+    assert_eq!(config.feeds.len(), 1);
+    assert_eq!(config.feeds[0].name, DEFAULT_FEED);
+    assert_eq!(
+        config.feeds[0].data_file_url,
+        "https://example.com/gtfs.zip"
+    );
+    assert_eq!(config.stops[0].feed, DEFAULT_FEED);
+    assert!(!config.stops[0].database.records.is_empty());
+
+    // Stored in the current layout, the data file path is moved to the
+    // feed state.
+    let content = fs::read_to_string(conf_dir.join("config.yaml")).unwrap();
+    assert!(content.contains("feeds:"));
+    assert!(!content.contains("data_file_path"));
+
+    let config = Config::load().await.unwrap().unwrap();
+    assert_eq!(config.feeds[0].data_file_path, fixture("feed"));
+}
+
+#[tokio::test]
+async fn feed_state_is_moved_out_of_config() {
+    let home = home("state");
+    let conf_dir = home.conf_dir();
+    fs::create_dir_all(&conf_dir).unwrap();
+
+    // Feeds layout that kept the data file path and version.
+    let older = format!(
+        "---\nfeeds:\n  - name: city\n    data_file_url: https://example.com/gtfs.zip\n    data_file_path: {}\n    version:\n      etag: \"v1\"\n      last_modified: ~\n      sha256: abc\nstops: []\n",
+        fixture("feed").display()
+    );
+    fs::write(conf_dir.join("config.yaml"), older).unwrap();
+
+    Config::load().await.unwrap().unwrap();
+
+    let content = fs::read_to_string(conf_dir.join("config.yaml")).unwrap();
+    assert!(!content.contains("data_file_path"));
+    assert!(!content.contains("sha256"));
+    assert!(!content.contains("etag"));
+    assert!(home.cache_dir().join("city.state").exists());
+
+    let config = Config::load().await.unwrap().unwrap();
+    assert_eq!(config.feeds[0].data_file_path, fixture("feed"));
+    assert_eq!(config.feeds[0].version.etag.as_deref(), Some("v1"));
+    assert_eq!(config.feeds[0].version.sha256, "abc");
+
+    // Without state the data file is looked for at the default path.
+    fs::remove_file(home.cache_dir().join("city.state")).unwrap();
+    let config = Config::load().await.unwrap().unwrap();
+    assert_eq!(config.feeds[0].data_file_path, conf_dir.join("city.gtfs"));
+    assert_eq!(config.feeds[0].version.sha256, "");
+}
+
+#[tokio::test]
+async fn failed_init_keeps_current_data_file() {
+    let home = home("init");
+    let good = home.dir.join("good.zip");
+    let bad = home.dir.join("bad.zip");
+    zip_feed(&good);
+    fs::write(&bad, "not a zip").unwrap();
+
+    Config::init(None, good.to_str().unwrap()).await.unwrap();
+    let data_file_path = Config::data_file_path(DEFAULT_FEED).unwrap();
+    let data_file = fs::read(&data_file_path).unwrap();
+
+    assert!(Config::init(None, bad.to_str().unwrap()).await.is_err());
+    assert_eq!(fs::read(&data_file_path).unwrap(), data_file);
+    assert!(!data_file_path.with_extension("gtfs.new").exists());
+
+    // Config still works.
+    let config = Config::load().await.unwrap().unwrap();
+    assert_eq!(config.feeds[0].data_file_url, good.to_str().unwrap());
+}
+
+/// Temporary directory of the given test.
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("transpors-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+/// Config with just the given feed.
+fn feed_config(data_file_url: String, data_file_path: &Path, version: DataFileVersion) -> Config {
+    Config {
+        feeds: vec![FeedSource {
+            name: DEFAULT_FEED.to_owned(),
+            data_file_url,
+            data_file_path: data_file_path.to_owned(),
+            version,
+            trip_updates_url: None,
+            alerts_url: None,
+            vehicle_positions_url: None,
+        }],
+        stops: vec![],
+    }
+}
+
+#[tokio::test]
+async fn unchanged_data_file_keeps_new_validators() {
+    const CONTENT: &[u8] = b"data file";
+
+    let dir = temp_dir("unchanged");
+    let data_file_path = dir.join("default.gtfs");
+    fs::write(&data_file_path, CONTENT).unwrap();
+
+    // Server sends the same content with a new ETag.
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|_| async {
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header("ETag", "\"v2\"")
+                    .body(Body::from(CONTENT))
+                    .unwrap(),
+            )
+        }))
+    }));
+    let url = format!("http://{}/gtfs.zip", server.local_addr());
+    tokio::spawn(server);
+
+    let mut config = feed_config(
+        url,
+        &data_file_path,
+        DataFileVersion {
+            etag: Some("\"v1\"".to_owned()),
+            last_modified: None,
+            sha256: format!("{:x}", Sha256::digest(CONTENT)),
+        },
+    );
+
+    let update = config.update_feed(DEFAULT_FEED).await.unwrap();
+
+    assert!(matches!(update, FeedUpdate::Unchanged));
+    assert_eq!(config.feeds[0].version.etag.as_deref(), Some("\"v2\""));
+    assert!(!data_file_path.with_extension("gtfs.new").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn invalid_data_file_is_discarded() {
+    let dir = temp_dir("invalid");
+    let data_file_path = dir.join("default.gtfs");
+    let invalid_path = dir.join("invalid.zip");
+    fs::write(&data_file_path, "current").unwrap();
+    fs::write(&invalid_path, "not a zip").unwrap();
+
+    let mut config = feed_config(
+        invalid_path.display().to_string(),
+        &data_file_path,
+        DataFileVersion::default(),
+    );
+
+    assert!(config.update_feed(DEFAULT_FEED).await.is_err());
+    assert!(!data_file_path.with_extension("gtfs.new").exists());
+    assert_eq!(fs::read_to_string(&data_file_path).unwrap(), "current");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn failed_feed_add_leaves_no_data_file() {
+    let home = home("feed-add");
+    let good = home.dir.join("good.zip");
+    let bad = home.dir.join("bad.zip");
+    zip_feed(&good);
+    fs::write(&bad, "not a zip").unwrap();
+
+    let mut config = Config::init(None, good.to_str().unwrap()).await.unwrap();
+    let data_file_path = Config::data_file_path("extra").unwrap();
+
+    assert!(config
+        .add_feed("extra", bad.to_str().unwrap())
+        .await
+        .is_err());
+    assert!(!data_file_path.exists());
+    assert!(!data_file_path.with_extension("gtfs.new").exists());
+    assert_eq!(config.feeds.len(), 1);
+}
+
+#[tokio::test]
+async fn feed_names_stay_in_config_directory() {
+    let home = home("feed-name");
+    let good = home.dir.join("good.zip");
+    zip_feed(&good);
+
+    let mut config = Config::init(None, good.to_str().unwrap()).await.unwrap();
+
+    for name in &["", "..", "../extra", "a/b", "a\\b", "x..y"] {
+        match config.add_feed(name, good.to_str().unwrap()).await {
+            Err(Error::InvalidInput(_)) => {}
+            _ => panic!("feed name {:?} accepted", name),
+        }
+    }
+
+    assert!(!home.conf_dir().join("../extra.gtfs").exists());
+    assert_eq!(config.feeds.len(), 1);
+}
+
+fn direction(route: &str, headsign: &str) -> RouteDirection {
+    RouteDirection {
+        route: route.to_owned(),
+        headsign: Some(headsign.to_owned()),
+    }
+}
+
+#[test]
+fn route_filter_shows_all_route_directions() {
+    let mut stop = stop(&feed(&["OAK"]), "OAK");
+    stop.routes = vec!["1".to_owned()];
+
+    assert!(stop.shows_direction("1", Some("Depot")));
+    assert!(stop.shows_direction("1", Some("City Park")));
+    assert!(!stop.shows_direction("2", Some("Depot")));
+}
+
+#[test]
+fn direction_filter_shows_just_the_direction() {
+    let mut stop = stop(&feed(&["OAK"]), "OAK");
+    stop.directions = vec![direction("1", "City Park")];
+
+    assert!(stop.shows_direction("1", Some("City Park")));
+    assert!(!stop.shows_direction("1", Some("Depot")));
+    assert!(!stop.shows_direction("1", None));
+}
+
+#[test]
+fn route_and_direction_filters_add_up() {
+    let mut stop = stop(&feed(&["OAK"]), "OAK");
+    stop.routes = vec!["2".to_owned()];
+    stop.directions = vec![direction("1", "City Park")];
+
+    assert!(stop.shows_direction("2", Some("Depot")));
+    assert!(stop.shows_direction("1", Some("City Park")));
+    assert!(!stop.shows_direction("1", Some("Depot")));
+}
+
+#[test]
+fn cleared_filters_show_everything() {
+    let mut stop = stop(&feed(&["OAK"]), "OAK");
+    stop.set_filters(vec!["1".to_owned()], vec![direction("1", "City Park")])
+        .unwrap();
+    stop.set_filters(vec![], vec![]).unwrap();
+
+    assert!(stop.routes.is_empty() && stop.directions.is_empty());
+    assert!(stop.shows_direction("1", Some("Depot")));
+    assert!(stop.shows_direction("2", None));
+}
+
+#[test]
+fn filters_of_unserved_routes_are_rejected() {
+    let mut stop = stop(&feed(&["OAK"]), "OAK");
+    stop.set_filters(vec!["1".to_owned()], vec![]).unwrap();
+
+    for (routes, directions) in [
+        (vec!["2".to_owned()], vec![]),
+        (vec![], vec![direction("1", "Nowhere")]),
+        (vec!["1".to_owned()], vec![direction("2", "Depot")]),
+    ] {
+        match stop.set_filters(routes, directions) {
+            Err(Error::InvalidInput(_)) => {}
+            _ => panic!("unserved filter accepted"),
+        }
+    }
+
+    // Rejected filters leave the current ones.
+    assert_eq!(stop.routes, vec!["1".to_owned()]);
+    assert!(stop.directions.is_empty());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use transpors::db::Database;
+use transpors::feed::Feed;
+
+use common::fixture;
+
+/// Database of the stop as JSON, so databases can be compared.
+fn database(feed: &Feed, stop_id: &str) -> serde_json::Value {
+    let database = Database::from(feed, &feed.gtfs.stops[stop_id]).unwrap();
+
+    serde_json::to_value(&database).unwrap()
+}
+
+#[test]
+fn for_stops_builds_same_databases_as_from_path() {
+    let full = Feed::from_path(&fixture("feed")).unwrap();
+
+    // Station with platforms, platforms, stops served by the frequency
+    // trip and by trips with stop times out of order.
+    for stop_id in &["MAIN", "MAIN_A", "MAIN_B", "OAK", "PARK", "DEPOT"] {
+        let streamed = Feed::for_stops(&fixture("feed"), &[stop_id]).unwrap();
+
+        assert_eq!(
+            database(&full, stop_id),
+            database(&streamed, stop_id),
+            "stop {}",
+            stop_id
+        );
+    }
+}
+
+#[test]
+fn station_database_merges_platforms() {
+    let feed = Feed::for_stops(&fixture("feed"), &["MAIN"]).unwrap();
+    let database = Database::from(&feed, &feed.gtfs.stops["MAIN"]).unwrap();
+
+    let platforms = |trip: &str| -> Vec<Option<&str>> {
+        database
+            .records
+            .iter()
+            .filter(|r| r.trip == trip)
+            .map(|r| r.platform.as_deref())
+            .collect()
+    };
+
+    assert_eq!(platforms("T1"), vec![Some("A")]);
+    assert_eq!(platforms("T3"), vec![Some("B")]);
+    // Frequency trip runs every 10 minutes for an hour.
+    assert_eq!(platforms("F1").len(), 6);
+}
@@ -0,0 +1,34 @@
+
+
+2.0!
+stop OAK***OAKR
+
+
+stop OAK 
+route R1**R1R
+
+
+route R1 
+route R9**R9R
+
+
+route R9 
+trip F1**"
+F1R
+	
+trip F1 
+trip T9**"
+T9R
+	
+trip T9"
+	agency CT**
+CTR
+
+	agency CT"
+	agency XX**
+XXR
+
+	agency XX=
+stop OAK of route R9*%*	R9*OAKR
+
+stop OAK of route R9
\ No newline at end of file
@@ -0,0 +1,7 @@
+
+
+2.0,
+U2&
+
+F106:20:0020261019
+и
\ No newline at end of file
@@ -0,0 +1,6 @@
+
+
+2.0
+U1
+
+T120261019x
\ No newline at end of file
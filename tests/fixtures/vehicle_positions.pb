@@ -0,0 +1,7 @@
+
+
+2.0
+V1"
+
+T120261019
+ 
\ No newline at end of file
@@ -0,0 +1,71 @@
+//! Structured output compared with golden files in
+//! `tests/fixtures/output`, so schema changes don't go unnoticed. Run
+//! with `TRANSPORS_UPDATE_GOLDEN=1` to rewrite the files after an
+//! intended change (and bump `SCHEMA_VERSION` if it's not just an added
+//! field).
+
+mod common;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+use transpors::output::{self, Format};
+use transpors::realtime::Realtime;
+use transpors::timetables::Timetables;
+
+use common::{config, feed, fixture, query, stop, FEED};
+
+/// Departures from the MAIN station (with platforms) and PARK, with a
+/// delay and the vehicle at the first stop, written in the given format.
+async fn write(format: Format) -> String {
+    let feed = feed(&["MAIN", "PARK"]);
+    let mut timetables = Timetables::from(config(vec![stop(&feed, "MAIN"), stop(&feed, "PARK")]))
+        .await
+        .unwrap();
+    let mut realtime = Realtime::default();
+
+    for snapshot in &["trip_updates.pb", "vehicle_positions.pb"] {
+        realtime
+            .load(fixture(snapshot).to_str().unwrap())
+            .await
+            .unwrap();
+    }
+
+    let mut realtimes = HashMap::new();
+    realtimes.insert(FEED.to_owned(), realtime);
+    timetables.set_realtime(realtimes);
+
+    let query = query("2026-10-19 06:50");
+    let departures = timetables.get_departures(&query);
+    let mut out = vec![];
+    output::write(&mut out, format, &departures, query.at).unwrap();
+
+    String::from_utf8(out).unwrap()
+}
+
+/// Compares output with the golden file (or rewrites it).
+fn assert_golden(output: &str, name: &str) {
+    let path = fixture("output").join(name);
+
+    if env::var_os("TRANSPORS_UPDATE_GOLDEN").is_some() {
+        fs::write(&path, output).unwrap();
+    }
+
+    assert_eq!(output, fs::read_to_string(&path).unwrap(), "{}", name);
+}
+
+#[tokio::test]
+async fn json() {
+    assert_golden(&write(Format::Json).await, "departures.json");
+}
+
+#[tokio::test]
+async fn ndjson() {
+    assert_golden(&write(Format::Ndjson).await, "departures.ndjson");
+}
+
+#[tokio::test]
+async fn csv() {
+    assert_golden(&write(Format::Csv).await, "departures.csv");
+}
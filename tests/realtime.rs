@@ -0,0 +1,152 @@
+mod common;
+
+use chrono::Duration;
+use std::collections::HashMap;
+
+use transpors::realtime::{Proximity, Realtime};
+use transpors::timetables::{Departure, NextDeparture, Timetables};
+
+use common::{config, feed, fixture, query, stop, FEED};
+
+/// Timetables of the given fixture stop with the given realtime snapshot
+/// loaded.
+async fn timetables(stop_id: &str, snapshot: &str) -> Timetables {
+    let feed = feed(&[stop_id]);
+    let mut timetables = Timetables::from(config(vec![stop(&feed, stop_id)]))
+        .await
+        .unwrap();
+    let mut realtime = Realtime::default();
+    realtime
+        .load(fixture(snapshot).to_str().unwrap())
+        .await
+        .unwrap();
+
+    let mut realtimes = HashMap::new();
+    realtimes.insert(FEED.to_owned(), realtime);
+    timetables.set_realtime(realtimes);
+
+    timetables
+}
+
+/// Next departure of the given trip.
+fn next<'a>(departures: &'a [Departure], trip: &str) -> &'a NextDeparture<'a> {
+    departures[0]
+        .departures
+        .iter()
+        .find(|d| d.record.trip == trip)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn stops_away_counts_stops_not_sequences() {
+    // Stop sequences of the trip are 10, 20, 30, 40 and the vehicle
+    // stands at the first stop.
+    let timetables = timetables("PARK", "vehicle_positions.pb").await;
+    let departures = timetables.get_departures(&query("2026-10-19 06:50"));
+    let next = next(&departures, "T1");
+
+    assert_eq!(next.record.position, 2);
+    assert_eq!(next.proximity, Some(Proximity::StopsAway(2)));
+}
+
+#[tokio::test]
+async fn vehicle_at_stop() {
+    let timetables = timetables("MAIN_A", "vehicle_positions.pb").await;
+    let departures = timetables.get_departures(&query("2026-10-19 06:50"));
+
+    assert_eq!(next(&departures, "T1").proximity, Some(Proximity::AtStop));
+}
+
+#[tokio::test]
+async fn delay_propagates_from_preceding_stop() {
+    // Delay is given for the stop before.
+    let timetables = timetables("PARK", "trip_updates.pb").await;
+    let departures = timetables.get_departures(&query("2026-10-19 06:50"));
+    let next = next(&departures, "T1");
+
+    assert_eq!(next.delay(), Some(Duration::minutes(2)));
+    assert_eq!(next.expected_time().format("%H:%M").to_string(), "07:12");
+}
+
+#[tokio::test]
+async fn frequency_trip_update_applies_to_its_start_only() {
+    // Trips start every 10 minutes since 06:00, the update is for the
+    // one starting at 06:20 (at the stop at 06:25).
+    let timetables = timetables("OAK", "frequency_trip_updates.pb").await;
+    let departures = timetables.get_departures(&query("2026-10-19 06:15"));
+    let frequency_trips: Vec<&NextDeparture> = departures[0]
+        .departures
+        .iter()
+        .filter(|d| d.record.trip == "F1")
+        .collect();
+
+    assert_eq!(frequency_trips.len(), 4);
+
+    for departure in frequency_trips {
+        match departure.record.trip_start {
+            Some(start) if start == 6 * 3600 + 20 * 60 => {
+                assert_eq!(departure.delay(), Some(Duration::minutes(3)))
+            }
+            _ => assert_eq!(departure.realtime, None),
+        }
+    }
+}
+
+#[tokio::test]
+async fn out_of_range_time_falls_back_to_delay() {
+    // Update of the stop gives a time no date can have and a delay.
+    let timetables = timetables("OAK", "out_of_range_trip_updates.pb").await;
+    let departures = timetables.get_departures(&query("2026-10-19 06:56"));
+
+    assert_eq!(next(&departures, "T1").delay(), Some(Duration::minutes(2)));
+}
+
+/// Headers of alerts shown at the given fixture stop. Each alert of the
+/// snapshot is named after the entity it's for.
+async fn alerts(stop_id: &str) -> Vec<String> {
+    let timetables = timetables(stop_id, "alerts.pb").await;
+    let departures = timetables.get_departures(&query("2026-10-19 06:50"));
+
+    departures[0]
+        .alerts
+        .iter()
+        .map(|a| a.header.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn alerts_match_stop() {
+    assert!(alerts("OAK").await.contains(&"stop OAK".to_owned()));
+    assert!(!alerts("DEPOT").await.contains(&"stop OAK".to_owned()));
+}
+
+#[tokio::test]
+async fn alerts_match_route() {
+    let alerts = alerts("DEPOT").await;
+
+    assert!(alerts.contains(&"route R1".to_owned()));
+    assert!(!alerts.contains(&"route R9".to_owned()));
+}
+
+#[tokio::test]
+async fn alerts_match_trip() {
+    // Trip F1 doesn't call at DEPOT.
+    assert!(alerts("OAK").await.contains(&"trip F1".to_owned()));
+    assert!(!alerts("DEPOT").await.contains(&"trip F1".to_owned()));
+    assert!(!alerts("OAK").await.contains(&"trip T9".to_owned()));
+}
+
+#[tokio::test]
+async fn alerts_match_agency() {
+    let alerts = alerts("DEPOT").await;
+
+    assert!(alerts.contains(&"agency CT".to_owned()));
+    assert!(!alerts.contains(&"agency XX".to_owned()));
+}
+
+#[tokio::test]
+async fn alerts_match_all_selector_fields() {
+    assert!(!alerts("OAK")
+        .await
+        .contains(&"stop OAK of route R9".to_owned()));
+}
@@ -0,0 +1,29 @@
+use gtfs_structures::Stop;
+
+use transpors::search::Query;
+
+fn stop(name: &str) -> Stop {
+    Stop {
+        id: "S".to_owned(),
+        name: name.to_owned(),
+        ..Stop::default()
+    }
+}
+
+#[test]
+fn short_words_match_exactly() {
+    let station = stop("Nádraží Holešovice");
+
+    assert!(Query::new("nad").score(&station).is_some());
+    assert!(Query::new("nam").score(&station).is_none());
+    assert!(Query::new("hol nam").score(&station).is_none());
+}
+
+#[test]
+fn long_words_tolerate_typos() {
+    let square = stop("Náměstí Míru");
+
+    assert!(Query::new("namnesti").score(&square).is_some());
+    assert!(Query::new("mir namnesti").score(&square).is_some());
+    assert!(Query::new("namnesti hol").score(&square).is_none());
+}
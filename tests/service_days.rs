@@ -0,0 +1,38 @@
+//! Service days stop times count from, across daylight saving time
+//! changes and midnight.
+
+mod common;
+
+use common::{date, departures};
+
+#[tokio::test]
+async fn times_on_daylight_saving_time_end() {
+    // Clocks go back at 3:00 on Sunday, stop times still count from
+    // "noon minus 12h" (1:00 CEST), so 08:05 is 08:05 CET.
+    let sunday = departures("OAK", "2026-10-25 07:00").await;
+
+    assert_eq!(
+        sunday,
+        vec![("T2".to_owned(), date("2026-10-25"), "08:05".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn trips_after_midnight_belong_to_previous_service_day() {
+    // Trip departs at 25:10 of the Monday service day.
+    let tuesday = departures("OAK", "2026-10-20 01:00").await;
+    // Weekday service doesn't run on Saturday, its Friday trip does.
+    let saturday = departures("OAK", "2026-10-24 01:00").await;
+    // No weekday service on Sunday.
+    let monday = departures("OAK", "2026-10-19 01:00").await;
+
+    assert_eq!(
+        tuesday,
+        vec![("NIGHT".to_owned(), date("2026-10-19"), "01:10".to_owned())]
+    );
+    assert_eq!(
+        saturday,
+        vec![("NIGHT".to_owned(), date("2026-10-23"), "01:10".to_owned())]
+    );
+    assert!(monday.is_empty());
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use transpors::config::Stop;
+use transpors::db::Database;
+use transpors::timetables::{Board, FeedStatus, Query, Timetables};
+
+use common::{config, date, feed, query, stop};
+
+/// Status of the stop feed at the given local time.
+async fn status(stop: Stop, at: &str) -> FeedStatus {
+    let timetables = Timetables::from(config(vec![stop])).await.unwrap();
+
+    timetables.get_departures(&query(at))[0].status
+}
+
+#[tokio::test]
+async fn feed_status_follows_service_period() {
+    // Calendars of the feed run thru 2026.
+    let feed = feed(&["OAK"]);
+
+    assert_eq!(
+        status(stop(&feed, "OAK"), "2025-12-31 23:59").await,
+        FeedStatus::NotYetValid(date("2026-01-01"))
+    );
+    assert_eq!(
+        status(stop(&feed, "OAK"), "2026-01-01 00:00").await,
+        FeedStatus::Valid
+    );
+    assert_eq!(
+        status(stop(&feed, "OAK"), "2026-12-31 23:59").await,
+        FeedStatus::Valid
+    );
+    assert_eq!(
+        status(stop(&feed, "OAK"), "2027-01-01 00:00").await,
+        FeedStatus::Expired(date("2026-12-31"))
+    );
+}
+
+#[tokio::test]
+async fn feed_status_without_feed_validity() {
+    // Service period is taken from the stop calendars then.
+    let mut oak = stop(&feed(&["OAK"]), "OAK");
+    oak.database.validity = None;
+
+    assert_eq!(
+        status(oak, "2027-01-01 00:00").await,
+        FeedStatus::Expired(date("2026-12-31"))
+    );
+
+    // Nothing to tell the period from.
+    let mut empty = stop(&feed(&["OAK"]), "OAK");
+    empty.database = Database::default();
+
+    assert_eq!(status(empty, "2027-01-01 00:00").await, FeedStatus::Valid);
+}
+
+/// Trip IDs on the stop board at the given local time.
+async fn board(stop_id: &str, at: &str, board: Board, show_all: bool) -> Vec<String> {
+    let feed = feed(&[stop_id]);
+    let timetables = Timetables::from(config(vec![stop(&feed, stop_id)]))
+        .await
+        .unwrap();
+    let query = Query {
+        board,
+        show_all,
+        ..query(at)
+    };
+
+    timetables.get_departures(&query)[0]
+        .departures
+        .iter()
+        .map(|d| d.record.trip.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn departures_without_pickup_are_hidden() {
+    // Trip LIMITED only drops passengers off at OAK.
+    assert!(board("OAK", "2026-10-19 11:50", Board::Departures, false)
+        .await
+        .is_empty());
+    assert_eq!(
+        board("OAK", "2026-10-19 11:50", Board::Arrivals, false).await,
+        vec!["LIMITED"]
+    );
+}
+
+#[tokio::test]
+async fn arrivals_without_drop_off_are_hidden() {
+    // Trip LIMITED only picks passengers up at PARK.
+    assert!(board("PARK", "2026-10-19 11:50", Board::Arrivals, false)
+        .await
+        .is_empty());
+    assert_eq!(
+        board("PARK", "2026-10-19 11:50", Board::Departures, false).await,
+        vec!["LIMITED"]
+    );
+}
+
+#[tokio::test]
+async fn show_all_shows_stop_times_without_pickup_or_drop_off() {
+    assert_eq!(
+        board("OAK", "2026-10-19 11:50", Board::Departures, true).await,
+        vec!["LIMITED"]
+    );
+    assert_eq!(
+        board("PARK", "2026-10-19 11:50", Board::Arrivals, true).await,
+        vec!["LIMITED"]
+    );
+}